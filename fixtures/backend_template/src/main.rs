@@ -0,0 +1,36 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+fn respond(mut stream: TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn handle(mut stream: TcpStream) {
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf).unwrap_or(0);
+    let request = String::from_utf8_lossy(&buf[..n]);
+
+    match request.split_whitespace().take(2).collect::<Vec<&str>>()[..] {
+        ["GET", "/health"] => respond(stream, "200 OK", "{\"status\":\"ok\"}"),
+        _ => respond(stream, "404 Not Found", "{}"),
+    }
+}
+
+fn main() {
+    // Fixture servers stop themselves, killing `cargo run` leaves the binary orphaned
+    std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_secs(10));
+        std::process::exit(0);
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:8080").expect("Failed to bind port 8080");
+    for stream in listener.incoming().flatten() {
+        handle(stream);
+    }
+}
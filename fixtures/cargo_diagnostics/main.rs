@@ -0,0 +1,14 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+fn takes(s: &String) -> usize {
+    s.len()
+}
+
+fn main() {
+    let s = String::new();
+    let a = takes(s.clone());
+    let v = vec![1u8];
+    let n: u64 = v.len();
+    println!("{} {} {}", a, n, z);
+}
@@ -11,6 +11,7 @@ pub fn print_backend_webserver_code(_project_description_and_template: &str) {
     /// IMPORTANT: ONLY outputs the code in executable form.
     /// No other external libraries should be used. Write functions that fit with the description from the PROJECT_DESCRIPTION
     /// OUTPUT: Print ONLY the code, nothing else. This function ONLY prints code.
+    /// FILES: Split the code into modules such as models, handlers, storage and routes where that helps. Start every file with a header line such as // file: src/routes.rs, or # file: Cargo.toml when the manifest has to change. Code without a header goes to src/main.rs.
     println!(OUTPUT)
 }
 
@@ -23,6 +24,7 @@ pub fn print_improved_webserver_code(_project_description_and_template: &str) {
     ///   3. ONLY writes the code. No commentary.
     /// IMPORTANT: The following libraries are already installed. Does not use ANY libraries other than what was provided in the template
     ///   reqwest, serde, serde_json, tokio, actix-web, async-trait
+    /// FILES: Split the code into modules such as models, handlers, storage and routes where that helps. Start every file with a header line such as // file: src/routes.rs, or # file: Cargo.toml when the manifest has to change. Code without a header goes to src/main.rs.
     println!(OUTPUT)
 }
 
@@ -31,6 +33,7 @@ pub fn print_fixed_code(_broken_code_with_bugs: &str) {
     /// INPUT: Takes in Rust BROKEN_CODE and the ERROR_BUGS found
     /// FUNCTION: Removes bugs from code
     /// IMPORTANT: Only prints out the new and improved code. No commentary or anything else
+    /// FILES: BROKEN_CODE may hold several files, each starting with a header line such as // file: src/routes.rs. Print every file you change in full with its header, files left out stay as they are.
     println!(OUTPUT)
 }
 
@@ -111,3 +114,102 @@ pub fn print_rest_api_endpoints(_code_input: &str) {
     /// ]
     println!(OUTPUT)
 }
+
+#[ai_function]
+pub fn print_axum_webserver_code(_project_description_and_template: &str) {
+    /// INPUT: Takes in a PROJECT_DESCRIPTION and CODE_TEMPLATE for a website backend build written with axum
+    /// IMPORTANT: The backend code is ONLY an example. If the Project Description requires it, make as many changes as you like.
+    /// IMPORTANT: You do not need to follow the backend code exactly. Write functions that make sense for the users request if required.
+    /// FUNCTION: Takes an existing set of code marked as CODE_TEMPLATE and updates or re-writes it to work for the purpose in the PROJECT_DESCRIPTION
+    /// IMPORTANT: The following libraries are already installed
+    ///   reqwest, serde, serde_json, tokio, axum 0.7, tower-http (cors), async-trait
+    /// IMPORTANT: Handlers take extractors such as State, Path and Json. Routes are added with Router::new().route("/path/:id", get(handler)) and the server is started with axum::serve on a tokio TcpListener.
+    /// IMPORTANT: ONLY outputs the code in executable form.
+    /// No other external libraries should be used. Write functions that fit with the description from the PROJECT_DESCRIPTION
+    /// OUTPUT: Print ONLY the code, nothing else. This function ONLY prints code.
+    /// FILES: Split the code into modules such as models, handlers, storage and routes where that helps. Start every file with a header line such as // file: src/routes.rs, or # file: Cargo.toml when the manifest has to change. Code without a header goes to src/main.rs.
+    println!(OUTPUT)
+}
+
+#[ai_function]
+pub fn print_improved_axum_webserver_code(_project_description_and_template: &str) {
+    /// INPUT: Takes in a PROJECT_DESCRIPTION and CODE_TEMPLATE for a website backend build written with axum
+    /// FUNCTION: Performs the following tasks:
+    ///   1. Removes any bugs in the code and adds minor additional functionality
+    ///   2. Makes sure everything requested in the spec from a backend standpoint was followed. If not, add the feature. No code should be implemented later. Everything should be written now.
+    ///   3. ONLY writes the code. No commentary.
+    /// IMPORTANT: The following libraries are already installed. Does not use ANY libraries other than what was provided in the template
+    ///   reqwest, serde, serde_json, tokio, axum 0.7, tower-http (cors), async-trait
+    /// FILES: Split the code into modules such as models, handlers, storage and routes where that helps. Start every file with a header line such as // file: src/routes.rs, or # file: Cargo.toml when the manifest has to change. Code without a header goes to src/main.rs.
+    println!(OUTPUT)
+}
+
+#[ai_function]
+pub fn print_axum_rest_api_endpoints(_code_input: &str) {
+    /// INPUT: Takes in Rust webserver CODE_INPUT based on axum
+    /// FUNCTION: Prints out the JSON schema for url endpoints and their respective types
+    /// LOGIC: Script analyses all code and can categorize into the following object keys:
+    ///   "route": This represents the url path of the endpoint
+    ///   "is_route_dynamic": if a route has a path parameter in it such as :symbol or :id as an example, then this will be set to true
+    ///   "method": This represents the method being called
+    ///   "request_body": This represents the body of a post method request
+    ///   "response": This represents the output based upon the structs in the code and understanding the functions
+    /// IMPORTANT: One entry per method. A route such as .route("/item/:id", get(read_item).put(update_item)) has two entries.
+    /// IMPORTANT: Only prints out the JSON schema. No commentary or anything else.
+    /// MUST READ: All keys are strings. Even bool should be wrapped in double quotes as "bool"
+    /// EXAMPLE:
+    /// INPUT_CODE:
+    /// ...
+    /// pub struct Item {
+    ///   pub id: u64,
+    ///   pub name: String,
+    ///   pub completed: bool,
+    /// }
+    /// ...
+    /// let app = Router::new()
+    ///     .route("/item", post(create_item))
+    ///     .route("/item/:id", get(read_item).delete(delete_item))
+    ///     .route("/crypto", get(crypto))
+    ///     .with_state(state);
+    /// PRINTS JSON FORMATTED OUTPUT:
+    /// [
+    ///   {
+    ///     "route": "/item/:id",
+    ///     "is_route_dynamic": "true",
+    ///     "method": "get"
+    ///     "request_body": "None",
+    ///     "response": {
+    ///       "id": "number",
+    ///       "name": "string",
+    ///       "completed": "bool",
+    ///     }
+    ///   },
+    ///   {
+    ///     "route": "/item",
+    ///     "is_route_dynamic": "false",
+    ///     "method": "post",
+    ///     "request_body": {
+    ///       "id": "number",
+    ///       "name": "string",
+    ///       "completed": "bool",
+    ///     },
+    ///     "response": "None"
+    ///   },
+    ///   {
+    ///     "route": "/item/:id",
+    ///     "is_route_dynamic": "true",
+    ///     "method": "delete",
+    ///     "request_body": "None",
+    ///     "response": "None"
+    ///   },
+    ///   {
+    ///     "route": "/crypto",
+    ///     "is_route_dynamic": "false",
+    ///     "method": "get",
+    ///     "request_body": "None",
+    ///     "response": "not_provided"
+    ///   },
+    ///   ... // etc
+    /// ]
+    println!(OUTPUT)
+}
@@ -1,76 +1,84 @@
-use crate::models::general::llm::{APIResponse, ChatCompletion, Message};
-use dotenv::dotenv;
-use reqwest::Client;
-use std::env;
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{provider_for, ChunkSink};
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{LlmResponse, Message};
 
-use reqwest::header::{HeaderMap, HeaderValue};
 // Call Large language model (i.e GPT-4)
 
-//used so that it can hold any object which holds the error trait
-// dyn used for dynamic dispatch, decides which trait to use at runtime
-pub async fn call_gpt(messages: Vec<Message>) -> Result<String, Box<dyn std::error::Error + Send>> {
-    dotenv().ok();
-
-    // Extract api key info
-    let api_key: String = env::var("OPEN_AI_KEY").expect("Open ai key not found in variables");
-    let api_org: String = env::var("OPEN_AI_ORG").expect("Open ai org id not found");
-
-    let url: &str = "https://api.openai.com/v1/chat/completions";
-
-    let mut headers = HeaderMap::new();
-    // Create api key header
-    headers.insert(
-        "authorization",
-        HeaderValue::from_str(&format!("Bearer {}", api_key))
-            .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?,
-    );
-
-    // headers.insert(
-    //     "OpenAI-Organization",
-    //     HeaderValue::from_str(api_org.as_str())
-    //         .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?,
-    // );
-
-    // Create client
-
-    let client = Client::builder()
-        .default_headers(headers)
-        .build()
-        .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?;
-
-    let chat_completion: ChatCompletion = ChatCompletion {
-        model: "gpt-4o".to_string(),
-        messages,
-        temperature: 0.1,
-    };
+// Errors are classified (rate limit, server, auth ...) so callers can decide to retry
+pub async fn call_gpt(ai_function: &str, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+    // Provider (OpenAI, Anthropic, Ollama) and model are picked from LLM_PROVIDER or the
+    // ai_function's route
+    let provider = provider_for(ai_function)?;
+    provider.chat(messages).await
+}
 
-    // let res_raw = client
-    //     .post(url)
-    //     .json(&chat_completion)
-    //     .send()
-    //     .await
-    //     .unwrap();
-
-    // dbg!(res_raw.text().await.unwrap());
-    let res: APIResponse = client
-        .post(url)
-        .json(&chat_completion)
-        .send()
-        .await
-        .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?
-        .json()
-        .await
-        .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?;
+// Streams the completion into `on_chunk` where the provider supports it, returns the full text
+pub async fn call_gpt_stream(
+    ai_function: &str,
+    messages: Vec<Message>,
+    on_chunk: ChunkSink<'_>,
+) -> Result<LlmResponse, LlmError> {
+    let provider = provider_for(ai_function)?;
+    provider.chat_stream(messages, on_chunk).await
+}
 
-    Ok(res.choices[0].message.content.clone())
+// Answer constrained to the schema where the provider supports it, the caller still validates
+pub async fn call_gpt_structured(
+    ai_function: &str,
+    messages: Vec<Message>,
+    schema: &OutputSchema,
+) -> Result<LlmResponse, LlmError> {
+    let provider = provider_for(ai_function)?;
+    provider.chat_structured(messages, schema).await
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::apis::{
+        llm_provider::{with_provider, LlmProvider, ProviderConfig, ProviderKind},
+        mock_llm_server::MockLlmServer,
+        provider_openai::OpenAiProvider,
+        provider_replay::ReplayProvider,
+    };
+    use crate::helpers::test_support::{fixture_path, mock_openai};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn tests_local_server_without_key() {
+        let replay =
+            ReplayProvider::from_file(fixture_path("cassettes/call_to_openai.json"), false);
+        let server = MockLlmServer::start(Arc::new(replay.unwrap()))
+            .await
+            .unwrap();
+        let mut config = ProviderConfig::new(ProviderKind::OpenAi);
+        config.base_url = server.base_url.clone();
+        config.model = "llama-3.1-8b-instruct".to_string();
+        config.headers = vec![("X-Gateway-Token".to_string(), "abc".to_string())];
+        let provider = Arc::new(OpenAiProvider::new(&config).unwrap());
+
+        let models = provider.list_models().await.unwrap().unwrap();
+        assert!(models.contains(&"llama-3.1-8b-instruct".to_string()));
+
+        let messages: Vec<Message> = vec![Message {
+            role: "user".to_string(),
+            content: "Hi there, this is a test. Give me a short response".to_string(),
+        }];
+        let res = with_provider(provider, call_gpt("call_to_openai", messages))
+            .await
+            .unwrap();
+        assert_eq!(res.content, "Hello! This is a short response.");
+
+        // The hosted api still needs a key
+        assert!(OpenAiProvider::new(&ProviderConfig::new(ProviderKind::OpenAi)).is_err());
+    }
 
     #[tokio::test]
     async fn tests_call_to_openai() {
+        // Real OpenAI provider against the local chat completions stand-in
+        let (_server, provider) = mock_openai("call_to_openai").await;
+
         let message: Message = Message {
             role: "user".to_string(),
             content: "Hi there, this is a test. Give me a short response".to_string(),
@@ -78,21 +86,64 @@ mod test {
 
         let messages: Vec<Message> = vec![message];
 
-        let res = call_gpt(messages).await;
-
-        // if let (Ok(res_string)) = res {
-        //     dbg!(res_string);
-        //     assert!(true)
-        // } else {
-        //     assert!(false)
-        // }
+        let res = with_provider(provider, call_gpt("call_to_openai", messages)).await;
 
         match res {
-            Ok(res_string) => {
-                dbg!(res_string);
-                assert!(true)
+            Ok(res) => {
+                dbg!(&res);
+                assert_eq!(res.content, "Hello! This is a short response.");
+                assert_eq!(res.usage.map(|usage| usage.prompt_tokens), Some(17));
             }
-            Err(_) => assert!(false),
+            Err(_) => panic!("Failed to call llm provider"),
         }
     }
+
+    #[tokio::test]
+    async fn tests_structured_call_falls_back_without_json_schema() {
+        let replay =
+            ReplayProvider::from_file(fixture_path("cassettes/call_to_openai.json"), false);
+        let server = MockLlmServer::start_without_json_schema(Arc::new(replay.unwrap()))
+            .await
+            .unwrap();
+        let mut config = ProviderConfig::new(ProviderKind::OpenAi);
+        config.base_url = server.base_url.clone();
+        let provider = Arc::new(OpenAiProvider::new(&config).unwrap());
+
+        let messages: Vec<Message> = vec![Message {
+            role: "user".to_string(),
+            content: "Hi there, this is a test. Give me a short response".to_string(),
+        }];
+        let schema = OutputSchema::of::<String>("call_to_openai");
+        let res = with_provider(
+            provider,
+            call_gpt_structured("call_to_openai", messages, &schema),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.content, "Hello! This is a short response.");
+    }
+
+    #[tokio::test]
+    async fn tests_streaming_call_to_openai() {
+        let (_server, provider) = mock_openai("call_to_openai").await;
+        let messages: Vec<Message> = vec![Message {
+            role: "user".to_string(),
+            content: "Hi there, this is a test. Give me a short response".to_string(),
+        }];
+
+        let chunks: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let on_chunk = |text: &str| chunks.lock().unwrap().push(text.to_string());
+        let res = with_provider(
+            provider,
+            call_gpt_stream("call_to_openai", messages, &on_chunk),
+        )
+        .await
+        .unwrap();
+
+        let chunks = chunks.into_inner().unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), res.content);
+        assert_eq!(res.content, "Hello! This is a short response.");
+        assert_eq!(res.usage.map(|usage| usage.completion_tokens), Some(7));
+    }
 }
@@ -0,0 +1,47 @@
+use reqwest::{header::HeaderMap, Client, IntoUrl, RequestBuilder};
+use std::{sync::OnceLock, time::Duration};
+
+use crate::apis::llm_error::LlmError;
+
+// One connection pool for every provider and route, built on first use
+static SHARED_CLIENT: OnceLock<Client> = OnceLock::new();
+
+fn shared_client() -> Result<Client, LlmError> {
+    if let Some(client) = SHARED_CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client: Client = Client::builder().build()?;
+    Ok(SHARED_CLIENT.get_or_init(|| client).clone())
+}
+
+// The shared client with one provider's headers (auth, custom) and timeout on each request
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    headers: HeaderMap,
+    timeout: Duration,
+}
+
+impl HttpClient {
+    pub fn new(headers: HeaderMap, timeout: Duration) -> Result<Self, LlmError> {
+        Ok(Self {
+            client: shared_client()?,
+            headers,
+            timeout,
+        })
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client
+            .get(url)
+            .headers(self.headers.clone())
+            .timeout(self.timeout)
+    }
+
+    pub fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client
+            .post(url)
+            .headers(self.headers.clone())
+            .timeout(self.timeout)
+    }
+}
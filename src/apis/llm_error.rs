@@ -0,0 +1,212 @@
+use reqwest::{header::HeaderMap, StatusCode};
+use std::{fmt, time::Duration};
+
+use crate::apis::retry_policy::RetryPolicy;
+
+// Failure of a call to an llm provider, classified so callers know whether retrying can help
+#[derive(Debug)]
+pub enum LlmError {
+    // Missing key, unknown provider, unreadable cassette ...
+    Config(String),
+    // 401 / 403, retrying will not help
+    Auth {
+        status: u16,
+        message: String,
+    },
+    // 429, with the delay the server asked for if any
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    // 5xx and overloaded responses
+    Server {
+        status: u16,
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    // Any other 4xx
+    BadRequest {
+        status: u16,
+        message: String,
+    },
+    Timeout,
+    Network(String),
+    // Response body did not have the expected shape
+    InvalidResponse(String),
+    // Retry policy gave up, holds the last failure
+    Exhausted {
+        attempts: u32,
+        last: Box<LlmError>,
+    },
+}
+
+impl LlmError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited { .. } | Self::Server { .. } | Self::Timeout | Self::Network(_)
+        )
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } | Self::Server { retry_after, .. } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
+
+    // Maps an unsuccessful HTTP response onto an error kind
+    pub fn from_status(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        let code: u16 = status.as_u16();
+        match code {
+            401 | 403 => Self::Auth {
+                status: code,
+                message: body,
+            },
+            429 => Self::RateLimited {
+                retry_after: parse_retry_after(headers, RetryPolicy::from_env().max_delay),
+                message: body,
+            },
+            // 529 is Anthropic's "overloaded"
+            500..=599 => Self::Server {
+                status: code,
+                retry_after: parse_retry_after(headers, RetryPolicy::from_env().max_delay),
+                message: body,
+            },
+            _ => Self::BadRequest {
+                status: code,
+                message: body,
+            },
+        }
+    }
+}
+
+// Retry-After in seconds, or OpenAI's retry-after-ms, at most max_delay.
+// A value too large for a Duration, "inf" included, waits max_delay.
+pub fn parse_retry_after(headers: &HeaderMap, max_delay: Duration) -> Option<Duration> {
+    let header = |name: &str| -> Option<f64> {
+        let value: f64 = headers.get(name)?.to_str().ok()?.trim().parse().ok()?;
+        // Also false for NaN
+        (value >= 0.0).then_some(value)
+    };
+
+    let secs: f64 = header("retry-after-ms")
+        .map(|ms| ms / 1000.0)
+        .or_else(|| header("retry-after"))?;
+    Some(Duration::try_from_secs_f64(secs).map_or(max_delay, |delay| delay.min(max_delay)))
+}
+
+// Returns the response untouched on success, otherwise the classified error with the body
+pub async fn check_response(res: reqwest::Response) -> Result<reqwest::Response, LlmError> {
+    let status: StatusCode = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let headers: HeaderMap = res.headers().clone();
+    let body: String = res.text().await.unwrap_or_default();
+    Err(LlmError::from_status(status, &headers, body))
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout
+        } else if e.is_decode() {
+            Self::InvalidResponse(e.to_string())
+        } else if let Some(status) = e.status() {
+            Self::from_status(status, &HeaderMap::new(), e.to_string())
+        } else if e.is_builder() {
+            Self::Config(e.to_string())
+        } else {
+            Self::Network(e.to_string())
+        }
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(msg) => write!(f, "llm provider misconfigured: {}", msg),
+            Self::Auth { status, message } => {
+                write!(
+                    f,
+                    "llm provider rejected credentials ({}): {}",
+                    status, message
+                )
+            }
+            Self::RateLimited { message, .. } => {
+                write!(f, "llm provider rate limited: {}", message)
+            }
+            Self::Server {
+                status, message, ..
+            } => write!(f, "llm provider server error ({}): {}", status, message),
+            Self::BadRequest { status, message } => {
+                write!(f, "llm provider rejected request ({}): {}", status, message)
+            }
+            Self::Timeout => write!(f, "llm provider timed out"),
+            Self::Network(msg) => write!(f, "could not reach llm provider: {}", msg),
+            Self::InvalidResponse(msg) => write!(f, "unexpected llm provider response: {}", msg),
+            Self::Exhausted { attempts, last } => {
+                write!(f, "gave up after {} attempts: {}", attempts, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn tests_status_classification() {
+        let headers = HeaderMap::new();
+        let err = LlmError::from_status(StatusCode::UNAUTHORIZED, &headers, "bad key".to_string());
+        assert!(matches!(err, LlmError::Auth { status: 401, .. }));
+        assert!(!err.is_retryable());
+
+        let err = LlmError::from_status(StatusCode::BAD_GATEWAY, &headers, String::new());
+        assert!(err.is_retryable());
+
+        let err = LlmError::from_status(StatusCode::BAD_REQUEST, &headers, String::new());
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn tests_rate_limit_reads_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        let err = LlmError::from_status(StatusCode::TOO_MANY_REQUESTS, &headers, String::new());
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+
+        headers.insert("retry-after-ms", HeaderValue::from_static("250"));
+        assert_eq!(
+            parse_retry_after(&headers, Duration::from_secs(30)),
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn tests_huge_retry_after_waits_max_delay() {
+        let max_delay = Duration::from_secs(30);
+        for (name, value) in [
+            ("retry-after", "86400"),
+            ("retry-after", "1e30"),
+            ("retry-after", "inf"),
+            ("retry-after-ms", "1e300"),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_static(value));
+            assert_eq!(parse_retry_after(&headers, max_delay), Some(max_delay));
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("NaN"));
+        assert_eq!(parse_retry_after(&headers, max_delay), None);
+    }
+}
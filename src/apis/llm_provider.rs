@@ -0,0 +1,391 @@
+use async_trait::async_trait;
+use dotenv::dotenv;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::{
+    env,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use strum_macros::{Display, EnumString};
+
+use crate::apis::{
+    llm_error::LlmError,
+    llm_router::Router,
+    provider_anthropic::AnthropicProvider,
+    provider_cache::{CacheConfig, CachingProvider, RequestOptions},
+    provider_limit::LimitedProvider,
+    provider_ollama::OllamaProvider,
+    provider_openai::OpenAiProvider,
+    provider_replay::ReplayProvider,
+    rate_limit::{RateLimiter, RateLimits},
+    structured_output::OutputSchema,
+};
+use crate::models::general::llm::{LlmResponse, Message};
+
+// Any backend able to answer a list of chat messages with a single completion
+#[async_trait]
+pub trait LlmProvider: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &'static str;
+    fn model(&self) -> &str;
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError>;
+
+    // Same as chat, handing text to `on_chunk` as it is generated.
+    // Providers that cannot stream deliver the whole completion as one chunk.
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<LlmResponse, LlmError> {
+        let response: LlmResponse = self.chat(messages).await?;
+        on_chunk(&response.content);
+        Ok(response)
+    }
+
+    // Same as chat, constraining the answer to a JSON schema where the provider can.
+    // Others only have the ai_function prompt to go by, callers validate either way.
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        _schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        self.chat(messages).await
+    }
+
+    // Models the server offers, None where the provider has no way to list them
+    async fn list_models(&self) -> Result<Option<Vec<String>>, LlmError> {
+        Ok(None)
+    }
+}
+
+// Receives streamed text as it arrives
+pub type ChunkSink<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ProviderKind {
+    #[strum(serialize = "openai", serialize = "openai-compatible")]
+    OpenAi,
+    Anthropic,
+    Ollama,
+    // Answers from a recorded cassette (LLM_CASSETTE), no network
+    Replay,
+}
+
+impl ProviderKind {
+    pub fn default_base_url(&self) -> &'static str {
+        match self {
+            Self::OpenAi => "https://api.openai.com/v1",
+            Self::Anthropic => "https://api.anthropic.com/v1",
+            Self::Ollama => "http://localhost:11434",
+            Self::Replay => "",
+        }
+    }
+
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            Self::OpenAi => "gpt-4o",
+            Self::Anthropic => "claude-3-5-sonnet-latest",
+            Self::Ollama => "llama3.1",
+            Self::Replay => "cassette",
+        }
+    }
+
+    // Provider specific env var holding the api key
+    pub fn api_key_var(&self) -> Option<&'static str> {
+        match self {
+            Self::OpenAi => Some("OPEN_AI_KEY"),
+            Self::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Self::Ollama | Self::Replay => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub temperature: f32,
+    // Completion length cap, provider default when None
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+    // Per request timeout, reported as a retryable LlmError::Timeout
+    pub timeout: Duration,
+    // Cassette replayed by the replay provider
+    pub cassette: Option<String>,
+    // Cassette every exchange is recorded to
+    pub record_to: Option<String>,
+    // On-disk response cache, off unless configured
+    pub cache: Option<CacheConfig>,
+    // Sent with every request, e.g. for a gateway in front of a self-hosted server
+    pub headers: Vec<(String, String)>,
+    // Shared by every route, see Router
+    pub rate_limits: RateLimits,
+}
+
+impl ProviderConfig {
+    pub fn new(kind: ProviderKind) -> Self {
+        Self {
+            kind,
+            base_url: kind.default_base_url().to_string(),
+            api_key: None,
+            model: kind.default_model().to_string(),
+            temperature: 0.1,
+            max_tokens: None,
+            stop: vec![],
+            timeout: Duration::from_secs(120),
+            cassette: None,
+            record_to: None,
+            cache: None,
+            headers: vec![],
+            rate_limits: RateLimits::default(),
+        }
+    }
+
+    pub fn header_map(&self) -> Result<HeaderMap, LlmError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let header_error =
+                |e: &dyn std::fmt::Display| LlmError::Config(format!("header {}: {}", name, e));
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| header_error(&e))?,
+                HeaderValue::from_str(value).map_err(|e| header_error(&e))?,
+            );
+        }
+        Ok(headers)
+    }
+
+    // Reads LLM_PROVIDER, LLM_BASE_URL, LLM_MODEL, LLM_TEMPERATURE, LLM_TIMEOUT_SECS and LLM_API_KEY
+    // (falling back to the provider specific key variable), plus LLM_CASSETTE for replay
+    // and LLM_RECORD_CASSETTE to record a run.
+    // LLM_HEADERS adds headers ("Name: value; Other: value"), OPEN_AI_ORG the OpenAI organization.
+    // LLM_MAX_CONCURRENT, LLM_RPM and LLM_TPM limit traffic.
+    // Responses are cached in LLM_CACHE_DIR for LLM_CACHE_TTL_SECS unless LLM_CACHE=0 (--no-cache)
+    pub fn from_env() -> Result<Self, LlmError> {
+        dotenv().ok();
+
+        let kind: ProviderKind = match env::var("LLM_PROVIDER") {
+            Ok(value) => {
+                ProviderKind::from_str(value.trim()).map_err(|e| LlmError::Config(e.to_string()))?
+            }
+            Err(_) => ProviderKind::OpenAi,
+        };
+
+        let mut config = Self::new(kind);
+
+        if let Ok(base_url) = env::var("LLM_BASE_URL") {
+            config.base_url = base_url.trim_end_matches('/').to_string();
+        }
+        if let Ok(model) = env::var("LLM_MODEL") {
+            config.model = model;
+        }
+        if let Ok(temperature) = env::var("LLM_TEMPERATURE") {
+            config.temperature = temperature
+                .trim()
+                .parse()
+                .map_err(|e| LlmError::Config(format!("LLM_TEMPERATURE: {}", e)))?;
+        }
+        if let Ok(timeout) = env::var("LLM_TIMEOUT_SECS") {
+            let secs: u64 = timeout
+                .trim()
+                .parse()
+                .map_err(|e| LlmError::Config(format!("LLM_TIMEOUT_SECS: {}", e)))?;
+            config.timeout = Duration::from_secs(secs);
+        }
+        config.api_key = env::var("LLM_API_KEY")
+            .ok()
+            .or_else(|| kind.api_key_var().and_then(|var| env::var(var).ok()));
+        if let Ok(headers) = env::var("LLM_HEADERS") {
+            config.headers = parse_headers(&headers)?;
+        }
+        if let (ProviderKind::OpenAi, Ok(org)) = (kind, env::var("OPEN_AI_ORG")) {
+            config
+                .headers
+                .push(("OpenAI-Organization".to_string(), org.trim().to_string()));
+        }
+        config.rate_limits = RateLimits::from_env()?;
+        config.cassette = env::var("LLM_CASSETTE").ok();
+        config.record_to = env::var("LLM_RECORD_CASSETTE").ok();
+
+        let cache_off: bool =
+            env::var("LLM_CACHE").is_ok_and(|value| matches!(value.trim(), "0" | "false" | "off"));
+        if !cache_off {
+            let ttl_secs: u64 = match env::var("LLM_CACHE_TTL_SECS") {
+                Ok(ttl) => ttl
+                    .trim()
+                    .parse()
+                    .map_err(|e| LlmError::Config(format!("LLM_CACHE_TTL_SECS: {}", e)))?,
+                Err(_) => 7 * 24 * 60 * 60,
+            };
+            config.cache = Some(CacheConfig {
+                dir: env::var("LLM_CACHE_DIR")
+                    .unwrap_or_else(|_| ".llm_cache".to_string())
+                    .into(),
+                ttl: Duration::from_secs(ttl_secs),
+            });
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_headers(headers: &str) -> Result<Vec<(String, String)>, LlmError> {
+    headers
+        .split([';', '\n'])
+        .filter(|header| !header.trim().is_empty())
+        .map(|header| match header.split_once(':') {
+            Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+            None => Err(LlmError::Config(format!(
+                "LLM_HEADERS: expected \"Name: value\", got {}",
+                header.trim()
+            ))),
+        })
+        .collect()
+}
+
+// Provider for one config, held back by the limiter and behind the response cache when enabled,
+// so cache hits are never limited. Recording is left to the Router so that all routes share
+// one cassette.
+pub fn build_backend(
+    config: &ProviderConfig,
+    limiter: &Arc<RateLimiter>,
+) -> Result<Arc<dyn LlmProvider>, LlmError> {
+    let provider: Arc<dyn LlmProvider> = match config.kind {
+        ProviderKind::OpenAi => Arc::new(OpenAiProvider::new(config)?),
+        ProviderKind::Anthropic => Arc::new(AnthropicProvider::new(config)?),
+        ProviderKind::Ollama => Arc::new(OllamaProvider::new(config)?),
+        ProviderKind::Replay => {
+            let cassette: &String = config.cassette.as_ref().ok_or_else(|| {
+                LlmError::Config("LLM_CASSETTE not set for replay provider".to_string())
+            })?;
+            Arc::new(ReplayProvider::from_file(cassette, false)?)
+        }
+    };
+
+    if config.kind == ProviderKind::Replay {
+        return Ok(provider);
+    }
+    let provider: Arc<dyn LlmProvider> = Arc::new(LimitedProvider::new(provider, limiter.clone()));
+
+    // Replays are free already, recordings also capture answers that came from the cache
+    match &config.cache {
+        Some(cache) => Ok(Arc::new(CachingProvider::new(
+            provider,
+            cache.clone(),
+            RequestOptions::from_config(config),
+        ))),
+        _ => Ok(provider),
+    }
+}
+
+static ROUTER: OnceLock<Router> = OnceLock::new();
+
+tokio::task_local! {
+    static SCOPED_PROVIDER: Arc<dyn LlmProvider>;
+}
+
+// Runs a future with every llm call inside it going to the given provider (tests, replays)
+#[cfg(test)]
+pub async fn with_provider<F: std::future::Future>(
+    provider: Arc<dyn LlmProvider>,
+    fut: F,
+) -> F::Output {
+    SCOPED_PROVIDER.scope(provider, fut).await
+}
+
+// Providers configured for this process, built from the environment on first use
+fn router() -> Result<&'static Router, LlmError> {
+    if let Some(router) = ROUTER.get() {
+        return Ok(router);
+    }
+    let router = Router::from_env()?;
+    Ok(ROUTER.get_or_init(|| router))
+}
+
+// Default provider, used by ai_functions without a route
+pub fn current_provider() -> Result<Arc<dyn LlmProvider>, LlmError> {
+    if let Ok(provider) = SCOPED_PROVIDER.try_with(|provider| provider.clone()) {
+        return Ok(provider);
+    }
+    Ok(router()?.default_provider())
+}
+
+// Provider the routing table (LLM_ROUTES_FILE) picks for an ai_function
+pub fn provider_for(ai_function: &str) -> Result<Arc<dyn LlmProvider>, LlmError> {
+    if let Ok(provider) = SCOPED_PROVIDER.try_with(|provider| provider.clone()) {
+        return Ok(provider);
+    }
+    Ok(router()?.provider_for(ai_function))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_provider_kind_from_str() {
+        assert_eq!(
+            ProviderKind::from_str("openai").unwrap(),
+            ProviderKind::OpenAi
+        );
+        assert_eq!(
+            ProviderKind::from_str("OpenAI-Compatible").unwrap(),
+            ProviderKind::OpenAi
+        );
+        assert_eq!(
+            ProviderKind::from_str("anthropic").unwrap(),
+            ProviderKind::Anthropic
+        );
+        assert_eq!(
+            ProviderKind::from_str("ollama").unwrap(),
+            ProviderKind::Ollama
+        );
+        assert!(ProviderKind::from_str("palm").is_err());
+    }
+
+    #[test]
+    fn tests_build_provider_from_config() {
+        let mut config = ProviderConfig::new(ProviderKind::Ollama);
+        config.model = "mistral".to_string();
+        let limiter = Arc::new(RateLimiter::new(&RateLimits::default()));
+        let provider = build_backend(&config, &limiter).unwrap();
+        assert_eq!(provider.name(), "ollama");
+        assert_eq!(provider.model(), "mistral");
+
+        // Hosted providers refuse to start without a key
+        let config = ProviderConfig::new(ProviderKind::Anthropic);
+        assert!(build_backend(&config, &limiter).is_err());
+        let config = ProviderConfig::new(ProviderKind::Replay);
+        assert!(build_backend(&config, &limiter).is_err());
+    }
+
+    #[test]
+    fn tests_custom_headers() {
+        let headers = parse_headers("X-Gateway-Token: abc; X-Team: platform\n").unwrap();
+        assert_eq!(
+            headers,
+            vec![
+                ("X-Gateway-Token".to_string(), "abc".to_string()),
+                ("X-Team".to_string(), "platform".to_string()),
+            ]
+        );
+        assert!(parse_headers("X-Gateway-Token abc").is_err());
+
+        let mut config = ProviderConfig::new(ProviderKind::OpenAi);
+        config.headers = headers;
+        assert_eq!(config.header_map().unwrap()["x-team"], "platform");
+        config
+            .headers
+            .push(("bad header".to_string(), "x".to_string()));
+        assert!(config.header_map().is_err());
+    }
+
+    #[tokio::test]
+    async fn tests_scoped_provider_overrides_default() {
+        let replay: Arc<dyn LlmProvider> = Arc::new(ReplayProvider::new(Default::default(), true));
+        let name = with_provider(replay, async { current_provider().unwrap().name() }).await;
+        assert_eq!(name, "replay");
+    }
+}
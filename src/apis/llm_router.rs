@@ -0,0 +1,215 @@
+use dotenv::dotenv;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{build_backend, LlmProvider, ProviderConfig, ProviderKind};
+use crate::apis::provider_record::RecordingProvider;
+use crate::apis::rate_limit::RateLimiter;
+
+// Overrides for one ai_function, anything left out comes from the default provider config
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    pub provider: Option<String>,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    // Added to the headers of the provider config
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Route {
+    pub fn apply(&self, base: &ProviderConfig) -> Result<ProviderConfig, LlmError> {
+        let mut config: ProviderConfig = base.clone();
+
+        if let Some(provider) = &self.provider {
+            let kind: ProviderKind = ProviderKind::from_str(provider.trim())
+                .map_err(|e| LlmError::Config(format!("route provider {}: {}", provider, e)))?;
+            // Another provider starts from its own defaults and key, sharing only run settings
+            if kind != base.kind {
+                config = ProviderConfig::new(kind);
+                config.api_key = kind.api_key_var().and_then(|var| env::var(var).ok());
+                config.temperature = base.temperature;
+                config.timeout = base.timeout;
+                config.cache = base.cache.clone();
+                config.rate_limits = base.rate_limits;
+            }
+        }
+        if let Some(base_url) = &self.base_url {
+            config.base_url = base_url.trim_end_matches('/').to_string();
+        }
+        if let Some(model) = &self.model {
+            config.model = model.clone();
+        }
+        if let Some(temperature) = self.temperature {
+            config.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            config.max_tokens = Some(max_tokens);
+        }
+        if !self.stop.is_empty() {
+            config.stop = self.stop.clone();
+        }
+        config.headers.extend(
+            self.headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        Ok(config)
+    }
+}
+
+// ai_function name (as given by get_function_string!) to route, read from LLM_ROUTES_FILE, e.g.
+//   [convert_user_input_to_goal]
+//   model = "gpt-4o-mini"
+//   temperature = 0.0
+//
+//   [print_backend_webserver_code]
+//   provider = "anthropic"
+//   model = "claude-3-5-sonnet-latest"
+//   max_tokens = 8192
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct RoutingTable {
+    routes: HashMap<String, Route>,
+}
+
+impl RoutingTable {
+    pub fn from_toml(contents: &str) -> Result<Self, LlmError> {
+        toml::from_str(contents).map_err(|e| LlmError::Config(format!("routing table: {}", e)))
+    }
+
+    pub fn from_env() -> Result<Self, LlmError> {
+        dotenv().ok();
+        match env::var("LLM_ROUTES_FILE") {
+            Ok(path) => {
+                let contents: String = fs::read_to_string(&path)
+                    .map_err(|e| LlmError::Config(format!("routing table {}: {}", path, e)))?;
+                Self::from_toml(&contents)
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+// Default provider plus one provider per routed ai_function.
+// All routes are built up front so a bad route fails at startup, not halfway through a run.
+#[derive(Debug)]
+pub struct Router {
+    default: Arc<dyn LlmProvider>,
+    routes: HashMap<String, Arc<dyn LlmProvider>>,
+}
+
+impl Router {
+    pub fn new(base: &ProviderConfig, table: &RoutingTable) -> Result<Self, LlmError> {
+        // One limiter for all routes, they usually share an account's limits
+        let limiter: Arc<RateLimiter> = Arc::new(RateLimiter::new(&base.rate_limits));
+        let backend: Arc<dyn LlmProvider> = build_backend(base, &limiter)?;
+        // Every route records into the cassette of the default provider
+        let recorder: Option<RecordingProvider> = base
+            .record_to
+            .as_ref()
+            .map(|path| RecordingProvider::new(backend.clone(), path));
+
+        let mut routes: HashMap<String, Arc<dyn LlmProvider>> = HashMap::new();
+        // A replay answers whatever was recorded, whichever model that came from
+        if base.kind != ProviderKind::Replay {
+            for (ai_function, route) in &table.routes {
+                let routed: Arc<dyn LlmProvider> = build_backend(&route.apply(base)?, &limiter)?;
+                let routed: Arc<dyn LlmProvider> = match &recorder {
+                    Some(recorder) => Arc::new(recorder.sharing_cassette(routed)),
+                    None => routed,
+                };
+                routes.insert(ai_function.clone(), routed);
+            }
+        }
+
+        let default: Arc<dyn LlmProvider> = match recorder {
+            Some(recorder) => Arc::new(recorder),
+            None => backend,
+        };
+        Ok(Self { default, routes })
+    }
+
+    pub fn from_env() -> Result<Self, LlmError> {
+        Self::new(&ProviderConfig::from_env()?, &RoutingTable::from_env()?)
+    }
+
+    pub fn default_provider(&self) -> Arc<dyn LlmProvider> {
+        self.default.clone()
+    }
+
+    pub fn provider_for(&self, ai_function: &str) -> Arc<dyn LlmProvider> {
+        self.routes
+            .get(ai_function)
+            .unwrap_or(&self.default)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTES: &str = r#"
+[convert_user_input_to_goal]
+model = "llama3.2:1b"
+temperature = 0.0
+max_tokens = 256
+
+[print_backend_webserver_code]
+provider = "anthropic"
+model = "claude-3-5-sonnet-latest"
+stop = ["</code>"]
+"#;
+
+    #[test]
+    fn tests_route_overrides_default_config() {
+        let table = RoutingTable::from_toml(ROUTES).unwrap();
+        let base = ProviderConfig::new(ProviderKind::Ollama);
+
+        let config = table.routes["convert_user_input_to_goal"]
+            .apply(&base)
+            .unwrap();
+        assert_eq!(config.kind, ProviderKind::Ollama);
+        assert_eq!(config.model, "llama3.2:1b");
+        assert_eq!(config.temperature, 0.0);
+        assert_eq!(config.max_tokens, Some(256));
+
+        let config = table.routes["print_backend_webserver_code"]
+            .apply(&base)
+            .unwrap();
+        assert_eq!(config.kind, ProviderKind::Anthropic);
+        assert_eq!(config.base_url, ProviderKind::Anthropic.default_base_url());
+        assert_eq!(config.stop, vec!["</code>".to_string()]);
+
+        assert!(RoutingTable::from_toml("[print_site_urls]\nmodle = \"gpt-4o\"\n").is_err());
+    }
+
+    #[test]
+    fn tests_router_picks_provider_per_ai_function() {
+        let mut table = RoutingTable::from_toml(ROUTES).unwrap();
+        table.routes.remove("print_backend_webserver_code");
+        let router = Router::new(&ProviderConfig::new(ProviderKind::Ollama), &table).unwrap();
+
+        assert_eq!(
+            router.provider_for("convert_user_input_to_goal").model(),
+            "llama3.2:1b"
+        );
+        assert_eq!(
+            router.provider_for("print_project_scope").model(),
+            "llama3.1"
+        );
+        assert_eq!(router.default_provider().model(), "llama3.1");
+    }
+}
@@ -0,0 +1,199 @@
+use serde_json::json;
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::apis::provider_replay::{Interaction, ReplayProvider};
+use crate::models::general::llm::ChatCompletion;
+
+// Local stand-in for an OpenAI compatible server, answering /chat/completions from a cassette
+// and /models with a fixed list.
+// Lets the real HTTP provider code run without network access or keys.
+pub struct MockLlmServer {
+    pub base_url: String,
+    handle: JoinHandle<()>,
+}
+
+impl MockLlmServer {
+    pub async fn start(replay: Arc<ReplayProvider>) -> std::io::Result<Self> {
+        Self::serve(replay, true).await
+    }
+
+    // Like llama.cpp builds and other local servers that answer a json_schema
+    // response_format with a 400
+    pub async fn start_without_json_schema(replay: Arc<ReplayProvider>) -> std::io::Result<Self> {
+        Self::serve(replay, false).await
+    }
+
+    async fn serve(replay: Arc<ReplayProvider>, json_schema: bool) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}/v1", listener.local_addr()?);
+
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let replay = replay.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, replay, json_schema).await;
+                });
+            }
+        });
+
+        Ok(Self { base_url, handle })
+    }
+}
+
+impl Drop for MockLlmServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    replay: Arc<ReplayProvider>,
+    json_schema: bool,
+) -> std::io::Result<()> {
+    let (request_line, body) = read_request(&mut stream).await?;
+
+    let (status, payload) = match request_line.split_whitespace().nth(1) {
+        Some(path) if path.ends_with("/chat/completions") => {
+            match serde_json::from_slice::<ChatCompletion>(&body) {
+                Ok(completion) if completion.response_format.is_some() && !json_schema => (
+                    "400 Bad Request",
+                    json!({ "error": { "message": "response_format type json_schema is not supported" } }),
+                ),
+                Ok(completion) => match replay.next_interaction(&completion.messages) {
+                    Some(interaction) if completion.stream => {
+                        let body: String = event_stream(&interaction);
+                        return write_response(&mut stream, "200 OK", "text/event-stream", &body)
+                            .await;
+                    }
+                    Some(interaction) => (
+                        "200 OK",
+                        json!({
+                            "object": "chat.completion",
+                            "model": completion.model,
+                            "choices": [{
+                                "index": 0,
+                                "message": { "role": "assistant", "content": interaction.response },
+                                "finish_reason": "stop"
+                            }],
+                            "usage": usage_json(&interaction),
+                        }),
+                    ),
+                    None => (
+                        "404 Not Found",
+                        json!({ "error": { "message": "No recorded interaction left" } }),
+                    ),
+                },
+                Err(e) => (
+                    "400 Bad Request",
+                    json!({ "error": { "message": e.to_string() } }),
+                ),
+            }
+        }
+        Some(path) if path.ends_with("/models") => (
+            "200 OK",
+            json!({
+                "object": "list",
+                "data": [
+                    { "id": "gpt-4o", "object": "model" },
+                    { "id": "llama-3.1-8b-instruct", "object": "model" }
+                ]
+            }),
+        ),
+        _ => (
+            "404 Not Found",
+            json!({ "error": { "message": "Unknown route" } }),
+        ),
+    };
+
+    write_response(
+        &mut stream,
+        status,
+        "application/json",
+        &payload.to_string(),
+    )
+    .await
+}
+
+fn usage_json(interaction: &Interaction) -> Option<serde_json::Value> {
+    interaction.usage.map(|usage| {
+        json!({
+            "prompt_tokens": usage.prompt_tokens,
+            "completion_tokens": usage.completion_tokens,
+            "total_tokens": usage.prompt_tokens + usage.completion_tokens,
+        })
+    })
+}
+
+// Streamed answer: the response in a few deltas, usage in a last chunk, then [DONE]
+fn event_stream(interaction: &Interaction) -> String {
+    let mut body: String = String::new();
+    for word in interaction.response.split_inclusive(' ') {
+        let chunk = json!({
+            "object": "chat.completion.chunk",
+            "choices": [{ "index": 0, "delta": { "content": word } }]
+        });
+        body.push_str(&format!("data: {}\n\n", chunk));
+    }
+    let last = json!({ "object": "chat.completion.chunk", "choices": [], "usage": usage_json(interaction) });
+    body.push_str(&format!("data: {}\n\ndata: [DONE]\n\n", last));
+    body
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// Reads the request line and body of a single HTTP/1.1 request
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<(String, Vec<u8>)> {
+    let mut buf: Vec<u8> = vec![];
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let content_length: usize = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0);
+
+    while buf.len() < header_end + content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let request_line = head.lines().next().unwrap_or_default().to_string();
+    Ok((request_line, buf[header_end..].to_vec()))
+}
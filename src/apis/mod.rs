@@ -1 +1,18 @@
 pub mod call_request;
+pub mod http_client;
+pub mod llm_error;
+pub mod llm_provider;
+pub mod llm_router;
+#[cfg(test)]
+pub mod mock_llm_server;
+pub mod provider_anthropic;
+pub mod provider_cache;
+pub mod provider_limit;
+pub mod provider_ollama;
+pub mod provider_openai;
+pub mod provider_record;
+pub mod provider_replay;
+pub mod rate_limit;
+pub mod retry_policy;
+pub mod sse;
+pub mod structured_output;
@@ -0,0 +1,307 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde_json::json;
+
+use crate::apis::http_client::HttpClient;
+use crate::apis::llm_error::{check_response, LlmError};
+use crate::apis::llm_provider::{ChunkSink, LlmProvider, ProviderConfig};
+use crate::apis::sse::read_events;
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{
+    ANTRequest, ANTResponse, ANTStreamEvent, ANTUsage, LlmResponse, Message, TokenUsage,
+};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// The Messages API requires max_tokens, used unless the route sets one
+const MAX_TOKENS: u32 = 4096;
+
+#[derive(Debug)]
+pub struct AnthropicProvider {
+    client: HttpClient,
+    base_url: String,
+    model: String,
+    temperature: f32,
+    max_tokens: Option<u32>,
+    stop: Vec<String>,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: &ProviderConfig) -> Result<Self, LlmError> {
+        let api_key: &String = config.api_key.as_ref().ok_or_else(|| {
+            LlmError::Config("Anthropic api key not found in variables".to_string())
+        })?;
+
+        let mut headers: HeaderMap = config.header_map()?;
+        headers.insert(
+            "x-api-key",
+            HeaderValue::from_str(api_key).map_err(|e| LlmError::Config(e.to_string()))?,
+        );
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_static(ANTHROPIC_VERSION),
+        );
+
+        let client = HttpClient::new(headers, config.timeout)?;
+
+        Ok(Self {
+            client,
+            base_url: config.base_url.clone(),
+            model: config.model.clone(),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            stop: config.stop.clone(),
+        })
+    }
+
+    // The Messages API takes system prompts separately and needs at least one user turn,
+    // so a lone system prompt (as built by extend_ai_function) is sent as the user turn
+    pub fn build_request(&self, messages: Vec<Message>) -> ANTRequest {
+        let (system, mut turns): (Vec<Message>, Vec<Message>) =
+            messages.into_iter().partition(|msg| msg.role == "system");
+
+        let mut system: Option<String> = match system.is_empty() {
+            true => None,
+            false => Some(
+                system
+                    .into_iter()
+                    .map(|msg| msg.content)
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ),
+        };
+
+        if turns.is_empty() {
+            turns.push(Message {
+                role: "user".to_string(),
+                content: system.take().unwrap_or_default(),
+            });
+        }
+
+        ANTRequest {
+            model: self.model.clone(),
+            max_tokens: self.max_tokens.unwrap_or(MAX_TOKENS),
+            system,
+            messages: turns,
+            temperature: self.temperature,
+            stop_sequences: self.stop.clone(),
+            stream: false,
+            tools: vec![],
+            tool_choice: None,
+        }
+    }
+
+    async fn send(&self, request: ANTRequest) -> Result<ANTResponse, LlmError> {
+        let url: String = format!("{}/messages", self.base_url);
+        let res: reqwest::Response = self.client.post(url).json(&request).send().await?;
+        Ok(check_response(res).await?.json().await?)
+    }
+}
+
+// Folds one streamed event into the completion so far, false once the message is complete
+fn apply_stream_event(
+    event: ANTStreamEvent,
+    content: &mut String,
+    usage: &mut TokenUsage,
+    on_chunk: ChunkSink<'_>,
+) -> Result<bool, LlmError> {
+    match event {
+        ANTStreamEvent::MessageStart { message } => {
+            if let Some(start_usage) = message.usage {
+                usage.prompt_tokens = start_usage.input_tokens;
+            }
+        }
+        ANTStreamEvent::ContentBlockDelta { delta } => {
+            on_chunk(&delta.text);
+            content.push_str(&delta.text);
+        }
+        ANTStreamEvent::MessageDelta {
+            usage: Some(delta_usage),
+        } => usage.completion_tokens = delta_usage.output_tokens,
+        ANTStreamEvent::MessageStop => return Ok(false),
+        // Overloaded or failed mid-stream, the status was already 200 so retry like a dropped connection
+        ANTStreamEvent::Error { error } => {
+            return Err(LlmError::Network(format!(
+                "stream interrupted: {}",
+                error.message
+            )))
+        }
+        ANTStreamEvent::MessageDelta { usage: None } | ANTStreamEvent::Other => {}
+    }
+    Ok(true)
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        let res: ANTResponse = self.send(self.build_request(messages)).await?;
+        let usage: Option<TokenUsage> = res.usage.as_ref().map(ANTUsage::token_usage);
+
+        let content: String = res
+            .content
+            .into_iter()
+            .map(|block| block.text)
+            .collect::<Vec<String>>()
+            .join("");
+
+        Ok(LlmResponse {
+            content,
+            model: self.model.clone(),
+            usage,
+            cached: false,
+        })
+    }
+
+    // The schema goes in as the input of a forced tool, the tool call arguments are the answer
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        let mut request: ANTRequest = self.build_request(messages);
+        request.tools = vec![json!({
+            "name": schema.name,
+            "description": "Print the result of the function",
+            "input_schema": schema.object_schema(),
+        })];
+        request.tool_choice = Some(json!({ "type": "tool", "name": schema.name }));
+
+        let res: ANTResponse = self.send(request).await?;
+        let usage: Option<TokenUsage> = res.usage.as_ref().map(ANTUsage::token_usage);
+        let input: serde_json::Value = res
+            .content
+            .into_iter()
+            .find_map(|block| block.input)
+            .ok_or_else(|| LlmError::InvalidResponse("No tool_use block returned".to_string()))?;
+
+        Ok(LlmResponse {
+            content: schema.unwrap_value(input).to_string(),
+            model: self.model.clone(),
+            usage,
+            cached: false,
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<LlmResponse, LlmError> {
+        let url: String = format!("{}/messages", self.base_url);
+        let mut request: ANTRequest = self.build_request(messages);
+        request.stream = true;
+
+        let res: reqwest::Response = self.client.post(url).json(&request).send().await?;
+        let mut content: String = String::new();
+        let mut usage: TokenUsage = TokenUsage::default();
+
+        read_events(check_response(res).await?, |event| {
+            let event: ANTStreamEvent = serde_json::from_str(&event.data)
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+            apply_stream_event(event, &mut content, &mut usage, on_chunk)
+        })
+        .await?;
+
+        Ok(LlmResponse {
+            content,
+            model: self.model.clone(),
+            usage: Some(usage),
+            cached: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::llm_provider::ProviderKind;
+    use crate::apis::sse::SseDecoder;
+    use std::sync::Mutex;
+
+    fn provider() -> AnthropicProvider {
+        let mut config = ProviderConfig::new(ProviderKind::Anthropic);
+        config.api_key = Some("test-key".to_string());
+        AnthropicProvider::new(&config).unwrap()
+    }
+
+    #[test]
+    fn tests_lone_system_prompt_becomes_user_turn() {
+        let request = provider().build_request(vec![Message {
+            role: "system".to_string(),
+            content: "FUNCTION print_project_scope".to_string(),
+        }]);
+
+        assert_eq!(request.system, None);
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].role, "user");
+        assert_eq!(request.messages[0].content, "FUNCTION print_project_scope");
+    }
+
+    #[test]
+    fn tests_system_prompt_split_from_turns() {
+        let request = provider().build_request(vec![
+            Message {
+                role: "system".to_string(),
+                content: "You are a function printer".to_string(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: "Hi there".to_string(),
+            },
+        ]);
+
+        assert_eq!(
+            request.system,
+            Some("You are a function printer".to_string())
+        );
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].role, "user");
+    }
+
+    #[test]
+    fn tests_stream_events_assemble_completion() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"fn main\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"() {}\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{},\"usage\":{\"output_tokens\":6}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        let chunks: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let on_chunk = |text: &str| chunks.lock().unwrap().push(text.to_string());
+        let mut content = String::new();
+        let mut usage = TokenUsage::default();
+        let mut finished = false;
+
+        for event in SseDecoder::default().push(body.as_bytes()) {
+            let event: ANTStreamEvent = serde_json::from_str(&event.data).unwrap();
+            finished = !apply_stream_event(event, &mut content, &mut usage, &on_chunk).unwrap();
+        }
+
+        assert!(finished);
+        assert_eq!(content, "fn main() {}");
+        assert_eq!(chunks.lock().unwrap().len(), 2);
+        assert_eq!(usage.prompt_tokens, 25);
+        assert_eq!(usage.completion_tokens, 6);
+
+        let error: ANTStreamEvent = serde_json::from_str(
+            "{\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}",
+        )
+        .unwrap();
+        let err = apply_stream_event(error, &mut content, &mut usage, &on_chunk).unwrap_err();
+        assert!(err.is_retryable());
+    }
+}
@@ -0,0 +1,294 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{ChunkSink, LlmProvider, ProviderConfig};
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{LlmResponse, Message, TokenUsage};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    created_at: u64,
+    model: String,
+    response: String,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+// Settings of the route sent with every request, they change the answer as much as the prompt.
+// The same model name on two servers is two different models.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestOptions {
+    pub base_url: String,
+    pub temperature: f32,
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+}
+
+impl RequestOptions {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            base_url: config.base_url.clone(),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            stop: config.stop.clone(),
+        }
+    }
+}
+
+// Answers repeated requests from disk. Entries are addressed by a hash of provider, model,
+// endpoint, request options and the full message list, so any change to a prompt is a miss.
+#[derive(Debug)]
+pub struct CachingProvider {
+    inner: Arc<dyn LlmProvider>,
+    config: CacheConfig,
+    options: RequestOptions,
+}
+
+impl CachingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, config: CacheConfig, options: RequestOptions) -> Self {
+        Self {
+            inner,
+            config,
+            options,
+        }
+    }
+
+    // The output schema, when there is one, is part of the request too
+    pub fn key(&self, messages: &[Message], schema: Option<&OutputSchema>) -> String {
+        let mut hasher = Sha256::new();
+        for part in [self.inner.name(), self.inner.model()] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(serde_json::to_vec(&self.options).unwrap_or_default());
+        hasher.update(serde_json::to_vec(messages).unwrap_or_default());
+        if let Some(schema) = schema {
+            hasher.update(schema.schema.to_string().as_bytes());
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.config
+            .dir
+            .join(&key[..2])
+            .join(format!("{}.json", key))
+    }
+
+    // Missing, unreadable and expired entries are all misses
+    fn lookup(&self, key: &str) -> Option<LlmResponse> {
+        let contents: String = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+
+        let age: u64 = unix_now().saturating_sub(entry.created_at);
+        if age >= self.config.ttl.as_secs() {
+            return None;
+        }
+        Some(LlmResponse {
+            content: entry.response,
+            model: entry.model,
+            usage: entry.usage,
+            cached: true,
+        })
+    }
+
+    // A cache that cannot be written only costs money, it must not stop the run
+    fn store(&self, key: &str, response: &LlmResponse) {
+        let entry = CacheEntry {
+            created_at: unix_now(),
+            model: response.model.clone(),
+            response: response.content.clone(),
+            usage: response.usage,
+        };
+        let path: PathBuf = self.entry_path(key);
+        if let (Some(parent), Ok(contents)) = (path.parent(), serde_json::to_string(&entry)) {
+            let _ = fs::create_dir_all(parent).and_then(|_| fs::write(&path, contents));
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl LlmProvider for CachingProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        let key: String = self.key(&messages, None);
+        if let Some(response) = self.lookup(&key) {
+            return Ok(response);
+        }
+
+        let response: LlmResponse = self.inner.chat(messages).await?;
+        self.store(&key, &response);
+        Ok(response)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<LlmResponse, LlmError> {
+        let key: String = self.key(&messages, None);
+        if let Some(response) = self.lookup(&key) {
+            on_chunk(&response.content);
+            return Ok(response);
+        }
+
+        let response: LlmResponse = self.inner.chat_stream(messages, on_chunk).await?;
+        self.store(&key, &response);
+        Ok(response)
+    }
+
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        let key: String = self.key(&messages, Some(schema));
+        if let Some(response) = self.lookup(&key) {
+            return Ok(response);
+        }
+
+        let response: LlmResponse = self.inner.chat_structured(messages, schema).await?;
+        self.store(&key, &response);
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, LlmError> {
+        self.inner.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::provider_replay::{Cassette, Interaction, ReplayProvider};
+
+    fn msg(content: &str) -> Vec<Message> {
+        vec![Message {
+            role: "system".to_string(),
+            content: content.to_string(),
+        }]
+    }
+
+    fn caching_provider(name: &str, ttl: Duration) -> CachingProvider {
+        let dir = std::env::temp_dir().join(format!(
+            "auto_gippity_cache_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        // A strict cassette with a single answer fails any request that is not a cache hit
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                request: msg("FUNCTION print_project_scope"),
+                response: "{\"is_crud_required\": true}".to_string(),
+                model: None,
+                usage: None,
+            }],
+        };
+        let replay = Arc::new(ReplayProvider::new(cassette, true));
+        let options = RequestOptions {
+            base_url: "http://localhost:8000/v1".to_string(),
+            temperature: 0.1,
+            max_tokens: None,
+            stop: vec![],
+        };
+        CachingProvider::new(replay, CacheConfig { dir, ttl }, options)
+    }
+
+    #[tokio::test]
+    async fn tests_repeated_request_is_served_from_cache() {
+        let provider = caching_provider("hit", Duration::from_secs(60));
+
+        let first = provider
+            .chat(msg("FUNCTION print_project_scope"))
+            .await
+            .unwrap();
+        assert!(!first.cached);
+
+        let second = provider
+            .chat(msg("FUNCTION print_project_scope"))
+            .await
+            .unwrap();
+        assert!(second.cached);
+        assert_eq!(second.content, first.content);
+
+        assert_ne!(
+            provider.key(&msg("FUNCTION print_project_scope"), None),
+            provider.key(&msg("FUNCTION print_site_urls"), None)
+        );
+        // A route with another completion cap is a different request
+        let longer = CachingProvider::new(
+            provider.inner.clone(),
+            provider.config.clone(),
+            RequestOptions {
+                max_tokens: Some(8192),
+                ..provider.options.clone()
+            },
+        );
+        assert_ne!(
+            provider.key(&msg("FUNCTION print_project_scope"), None),
+            longer.key(&msg("FUNCTION print_project_scope"), None)
+        );
+        // So is the same model on another server
+        let elsewhere = CachingProvider::new(
+            provider.inner.clone(),
+            provider.config.clone(),
+            RequestOptions {
+                base_url: "http://gpu-box:8000/v1".to_string(),
+                ..provider.options.clone()
+            },
+        );
+        assert_ne!(
+            provider.key(&msg("FUNCTION print_project_scope"), None),
+            elsewhere.key(&msg("FUNCTION print_project_scope"), None)
+        );
+        let _ = fs::remove_dir_all(&provider.config.dir);
+    }
+
+    #[tokio::test]
+    async fn tests_expired_entries_are_misses() {
+        let provider = caching_provider("expired", Duration::ZERO);
+
+        provider
+            .chat(msg("FUNCTION print_project_scope"))
+            .await
+            .unwrap();
+        // Cassette is used up, so the second call only succeeds from cache
+        let res = provider.chat(msg("FUNCTION print_project_scope")).await;
+        assert!(res.is_err());
+        let _ = fs::remove_dir_all(&provider.config.dir);
+    }
+}
@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{ChunkSink, LlmProvider};
+use crate::apis::rate_limit::RateLimiter;
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{LlmResponse, Message};
+
+// Holds every request to the inner provider until the shared limiter has room for it
+#[derive(Debug)]
+pub struct LimitedProvider {
+    inner: Arc<dyn LlmProvider>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl LimitedProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+
+    fn settle(&self, estimated: u64, response: &Result<LlmResponse, LlmError>) {
+        let used: u64 = match response {
+            Ok(LlmResponse {
+                usage: Some(usage), ..
+            }) => usage.prompt_tokens + usage.completion_tokens,
+            _ => estimated,
+        };
+        self.limiter.settle(estimated, used);
+    }
+}
+
+// Prompt size before the provider reports usage, about four characters a token
+fn estimate_tokens(messages: &[Message]) -> u64 {
+    let chars: usize = messages.iter().map(|msg| msg.content.len()).sum();
+    (chars / 4) as u64
+}
+
+#[async_trait]
+impl LlmProvider for LimitedProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        let estimated: u64 = estimate_tokens(&messages);
+        let _permit = self.limiter.acquire(estimated).await?;
+        let response = self.inner.chat(messages).await;
+        self.settle(estimated, &response);
+        response
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<LlmResponse, LlmError> {
+        let estimated: u64 = estimate_tokens(&messages);
+        let _permit = self.limiter.acquire(estimated).await?;
+        let response = self.inner.chat_stream(messages, on_chunk).await;
+        self.settle(estimated, &response);
+        response
+    }
+
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        let estimated: u64 = estimate_tokens(&messages);
+        let _permit = self.limiter.acquire(estimated).await?;
+        let response = self.inner.chat_structured(messages, schema).await;
+        self.settle(estimated, &response);
+        response
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, LlmError> {
+        self.inner.list_models().await
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+
+use crate::apis::http_client::HttpClient;
+use crate::apis::llm_error::{check_response, LlmError};
+use crate::apis::llm_provider::{LlmProvider, ProviderConfig};
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{
+    LlmResponse, Message, OllamaChatRequest, OllamaChatResponse, OllamaOptions, OllamaTags,
+    TokenUsage,
+};
+
+// Ollama style /api/chat endpoint, no authentication beyond custom headers
+#[derive(Debug)]
+pub struct OllamaProvider {
+    client: HttpClient,
+    base_url: String,
+    model: String,
+    temperature: f32,
+    max_tokens: Option<u32>,
+    stop: Vec<String>,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &ProviderConfig) -> Result<Self, LlmError> {
+        let client = HttpClient::new(config.header_map()?, config.timeout)?;
+
+        Ok(Self {
+            client,
+            base_url: config.base_url.clone(),
+            model: config.model.clone(),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            stop: config.stop.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        self.send(messages, None).await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, LlmError> {
+        let url: String = format!("{}/api/tags", self.base_url);
+        let res: reqwest::Response = self.client.get(url).send().await?;
+        let res: OllamaTags = check_response(res).await?.json().await?;
+        Ok(Some(
+            res.models.into_iter().map(|model| model.name).collect(),
+        ))
+    }
+
+    // Ollama takes any JSON schema as `format`, no wrapping needed
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        self.send(messages, Some(schema.schema.clone())).await
+    }
+}
+
+impl OllamaProvider {
+    async fn send(
+        &self,
+        messages: Vec<Message>,
+        format: Option<serde_json::Value>,
+    ) -> Result<LlmResponse, LlmError> {
+        let url: String = format!("{}/api/chat", self.base_url);
+        let request: OllamaChatRequest = OllamaChatRequest {
+            model: self.model.clone(),
+            messages,
+            stream: false,
+            options: OllamaOptions {
+                temperature: self.temperature,
+                num_predict: self.max_tokens,
+                stop: self.stop.clone(),
+            },
+            format,
+        };
+
+        let res: reqwest::Response = self.client.post(url).json(&request).send().await?;
+        let res: OllamaChatResponse = check_response(res).await?.json().await?;
+
+        let usage: Option<TokenUsage> = match (res.prompt_eval_count, res.eval_count) {
+            (None, None) => None,
+            (prompt, completion) => Some(TokenUsage {
+                prompt_tokens: prompt.unwrap_or(0),
+                completion_tokens: completion.unwrap_or(0),
+            }),
+        };
+
+        Ok(LlmResponse {
+            content: res.message.content,
+            model: self.model.clone(),
+            usage,
+            cached: false,
+        })
+    }
+}
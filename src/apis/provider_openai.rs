@@ -0,0 +1,199 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::apis::http_client::HttpClient;
+use crate::apis::llm_error::{check_response, LlmError};
+use crate::apis::llm_provider::{ChunkSink, LlmProvider, ProviderConfig, ProviderKind};
+use crate::apis::sse::read_events;
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{
+    APIModelList, APIResponse, APIStreamChunk, ChatCompletion, LlmResponse, Message, StreamOptions,
+    TokenUsage,
+};
+
+// Any server speaking the OpenAI chat completions format
+#[derive(Debug)]
+pub struct OpenAiProvider {
+    client: HttpClient,
+    base_url: String,
+    model: String,
+    temperature: f32,
+    max_tokens: Option<u32>,
+    stop: Vec<String>,
+    // Set once a compatible server rejected a json_schema response format
+    no_json_schema: AtomicBool,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: &ProviderConfig) -> Result<Self, LlmError> {
+        let mut headers: HeaderMap = config.header_map()?;
+        // Create api key header. Self-hosted servers (llama.cpp, vLLM ...) often run without one.
+        match &config.api_key {
+            Some(api_key) => {
+                headers.insert(
+                    "authorization",
+                    HeaderValue::from_str(&format!("Bearer {}", api_key))
+                        .map_err(|e| LlmError::Config(e.to_string()))?,
+                );
+            }
+            None if config.base_url == ProviderKind::OpenAi.default_base_url() => {
+                return Err(LlmError::Config(
+                    "Open ai key not found in variables".to_string(),
+                ));
+            }
+            None => {}
+        }
+
+        let client = HttpClient::new(headers, config.timeout)?;
+
+        Ok(Self {
+            client,
+            base_url: config.base_url.clone(),
+            model: config.model.clone(),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            stop: config.stop.clone(),
+            no_json_schema: AtomicBool::new(false),
+        })
+    }
+}
+
+impl OpenAiProvider {
+    fn completion(&self, messages: Vec<Message>) -> ChatCompletion {
+        ChatCompletion {
+            model: self.model.clone(),
+            messages,
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            stop: self.stop.clone(),
+            stream: false,
+            stream_options: None,
+            response_format: None,
+        }
+    }
+
+    async fn complete(&self, chat_completion: ChatCompletion) -> Result<LlmResponse, LlmError> {
+        let url: String = format!("{}/chat/completions", self.base_url);
+        let res: reqwest::Response = self.client.post(url).json(&chat_completion).send().await?;
+        let res: APIResponse = check_response(res).await?.json().await?;
+
+        let content: String = res
+            .choices
+            .first()
+            .map(|choice| choice.message.content.clone())
+            .ok_or_else(|| {
+                LlmError::InvalidResponse("No choices returned by chat completion".to_string())
+            })?;
+
+        Ok(LlmResponse {
+            content,
+            model: self.model.clone(),
+            usage: res.usage.map(|usage| TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+            }),
+            cached: false,
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        self.complete(self.completion(messages)).await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, LlmError> {
+        let url: String = format!("{}/models", self.base_url);
+        let res: reqwest::Response = self.client.get(url).send().await?;
+        let res: APIModelList = check_response(res).await?.json().await?;
+        Ok(Some(res.data.into_iter().map(|model| model.id).collect()))
+    }
+
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        if !self.no_json_schema.load(Ordering::Relaxed) {
+            let mut chat_completion: ChatCompletion = self.completion(messages.clone());
+            chat_completion.response_format = Some(json!({
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "schema": schema.object_schema() }
+            }));
+
+            match self.complete(chat_completion).await {
+                Ok(mut response) => {
+                    response.content = schema.unwrap_content(response.content);
+                    return Ok(response);
+                }
+                // Local servers without structured output: the ai_function prompt has to do,
+                // the caller validates and repairs the answer
+                Err(LlmError::BadRequest {
+                    status: 400 | 422, ..
+                }) if self.base_url != ProviderKind::OpenAi.default_base_url() => {
+                    self.no_json_schema.store(true, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.chat(messages).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<LlmResponse, LlmError> {
+        let url: String = format!("{}/chat/completions", self.base_url);
+        let mut chat_completion: ChatCompletion = self.completion(messages);
+        chat_completion.stream = true;
+        chat_completion.stream_options = Some(StreamOptions {
+            include_usage: true,
+        });
+
+        let res: reqwest::Response = self.client.post(url).json(&chat_completion).send().await?;
+        let mut content: String = String::new();
+        let mut usage: Option<TokenUsage> = None;
+
+        read_events(check_response(res).await?, |event| {
+            if event.data == "[DONE]" {
+                return Ok(false);
+            }
+            let chunk: APIStreamChunk = serde_json::from_str(&event.data)
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+
+            for choice in chunk.choices {
+                if let Some(text) = choice.delta.content {
+                    on_chunk(&text);
+                    content.push_str(&text);
+                }
+            }
+            if let Some(chunk_usage) = chunk.usage {
+                usage = Some(TokenUsage {
+                    prompt_tokens: chunk_usage.prompt_tokens,
+                    completion_tokens: chunk_usage.completion_tokens,
+                });
+            }
+            Ok(true)
+        })
+        .await?;
+
+        Ok(LlmResponse {
+            content,
+            model: self.model.clone(),
+            usage,
+            cached: false,
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use std::{path::PathBuf, sync::Arc, sync::Mutex};
+
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{ChunkSink, LlmProvider};
+use crate::apis::provider_replay::{Cassette, Interaction};
+use crate::apis::structured_output::OutputSchema;
+use crate::models::general::llm::{LlmResponse, Message};
+
+// Passes requests through to a real provider and writes every exchange to a cassette.
+// The file is rewritten after each call so an aborted run still leaves a usable cassette.
+#[derive(Debug)]
+pub struct RecordingProvider {
+    inner: Arc<dyn LlmProvider>,
+    path: PathBuf,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl RecordingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            cassette: Arc::new(Mutex::new(Cassette::default())),
+        }
+    }
+
+    // Records another provider into the same cassette, keeping one file in call order
+    pub fn sharing_cassette(&self, inner: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            inner,
+            path: self.path.clone(),
+            cassette: self.cassette.clone(),
+        }
+    }
+
+    fn record(&self, messages: Vec<Message>, response: &LlmResponse) -> Result<(), LlmError> {
+        // Saved under the lock so recorders sharing the cassette never write an older copy last
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.interactions.push(Interaction {
+            request: messages,
+            response: response.content.clone(),
+            model: Some(response.model.clone()),
+            usage: response.usage,
+        });
+        cassette.save(&self.path)
+    }
+
+    #[cfg(test)]
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RecordingProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        let response: LlmResponse = self.inner.chat(messages.clone()).await?;
+        self.record(messages, &response)?;
+        Ok(response)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<LlmResponse, LlmError> {
+        let response: LlmResponse = self.inner.chat_stream(messages.clone(), on_chunk).await?;
+        self.record(messages, &response)?;
+        Ok(response)
+    }
+
+    async fn chat_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+    ) -> Result<LlmResponse, LlmError> {
+        let response: LlmResponse = self.inner.chat_structured(messages.clone(), schema).await?;
+        self.record(messages, &response)?;
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, LlmError> {
+        self.inner.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::provider_replay::ReplayProvider;
+    use crate::models::general::llm::TokenUsage;
+
+    #[tokio::test]
+    async fn tests_recording_round_trips_through_replay() {
+        let path =
+            std::env::temp_dir().join(format!("auto_gippity_record_{}.json", std::process::id()));
+        let source = Cassette {
+            interactions: vec![Interaction {
+                request: vec![Message {
+                    role: "user".to_string(),
+                    content: "Hi there".to_string(),
+                }],
+                response: "Hello".to_string(),
+                model: None,
+                usage: Some(TokenUsage {
+                    prompt_tokens: 3,
+                    completion_tokens: 1,
+                }),
+            }],
+        };
+
+        let recorder = RecordingProvider::new(Arc::new(ReplayProvider::new(source, true)), &path);
+        let res = recorder
+            .chat(vec![Message {
+                role: "user".to_string(),
+                content: "Hi there".to_string(),
+            }])
+            .await
+            .unwrap();
+
+        assert_eq!(res.content, "Hello");
+        assert_eq!(res.usage.unwrap().completion_tokens, 1);
+        assert_eq!(Cassette::load(&path).unwrap(), recorder.cassette());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, sync::Mutex};
+
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::LlmProvider;
+use crate::models::general::llm::{LlmResponse, Message, TokenUsage};
+
+// One recorded request/response pair
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Interaction {
+    pub request: Vec<Message>,
+    pub response: String,
+    // Model that answered and what it cost, so replayed runs keep their accounting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+}
+
+// JSON file of interactions in the order they happened
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LlmError> {
+        let path = path.as_ref();
+        let cassette_error = |e: &dyn std::fmt::Display| {
+            LlmError::Config(format!("cassette {}: {}", path.display(), e))
+        };
+
+        let contents: String = fs::read_to_string(path).map_err(|e| cassette_error(&e))?;
+        serde_json::from_str(&contents).map_err(|e| cassette_error(&e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LlmError> {
+        let path = path.as_ref();
+        let cassette_error = |e: &dyn std::fmt::Display| {
+            LlmError::Config(format!("cassette {}: {}", path.display(), e))
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| cassette_error(&e))?;
+        }
+        let contents: String =
+            serde_json::to_string_pretty(self).map_err(|e| cassette_error(&e))?;
+        fs::write(path, contents).map_err(|e| cassette_error(&e))
+    }
+}
+
+// Answers from a cassette instead of the network.
+// A request is matched against unused interactions with identical messages first;
+// unless strict, it otherwise takes the next unused interaction in recorded order,
+// so cassettes survive small prompt edits.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    interactions: Vec<Interaction>,
+    used: Mutex<Vec<bool>>,
+    strict: bool,
+}
+
+impl ReplayProvider {
+    pub fn new(cassette: Cassette, strict: bool) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        Self {
+            interactions: cassette.interactions,
+            used: Mutex::new(used),
+            strict,
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>, strict: bool) -> Result<Self, LlmError> {
+        Ok(Self::new(Cassette::load(path)?, strict))
+    }
+
+    pub fn next_interaction(&self, messages: &[Message]) -> Option<Interaction> {
+        let mut used = self.used.lock().unwrap();
+
+        let exact = (0..self.interactions.len())
+            .find(|&i| !used[i] && self.interactions[i].request == messages);
+        let index = match (exact, self.strict) {
+            (Some(i), _) => Some(i),
+            (None, false) => used.iter().position(|is_used| !is_used),
+            (None, true) => None,
+        }?;
+
+        used[index] = true;
+        Some(self.interactions[index].clone())
+    }
+
+    #[cfg(test)]
+    pub fn remaining(&self) -> usize {
+        self.used.lock().unwrap().iter().filter(|u| !**u).count()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ReplayProvider {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn model(&self) -> &str {
+        "cassette"
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<LlmResponse, LlmError> {
+        let interaction: Interaction = self.next_interaction(&messages).ok_or_else(|| {
+            LlmError::Config("No recorded interaction left for request".to_string())
+        })?;
+
+        Ok(LlmResponse {
+            content: interaction.response,
+            model: interaction
+                .model
+                .unwrap_or_else(|| self.model().to_string()),
+            usage: interaction.usage,
+            cached: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(content: &str) -> Vec<Message> {
+        vec![Message {
+            role: "system".to_string(),
+            content: content.to_string(),
+        }]
+    }
+
+    fn cassette() -> Cassette {
+        Cassette {
+            interactions: vec![
+                Interaction {
+                    request: msg("first"),
+                    response: "one".to_string(),
+                    model: None,
+                    usage: None,
+                },
+                Interaction {
+                    request: msg("second"),
+                    response: "two".to_string(),
+                    model: None,
+                    usage: None,
+                },
+            ],
+        }
+    }
+
+    fn next_response(replay: &ReplayProvider, content: &str) -> Option<String> {
+        replay
+            .next_interaction(&msg(content))
+            .map(|interaction| interaction.response)
+    }
+
+    #[test]
+    fn tests_replay_prefers_exact_match() {
+        let replay = ReplayProvider::new(cassette(), false);
+        assert_eq!(next_response(&replay, "second"), Some("two".to_string()));
+        assert_eq!(next_response(&replay, "edited"), Some("one".to_string()));
+        assert_eq!(next_response(&replay, "first"), None);
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[test]
+    fn tests_strict_replay_rejects_unknown_request() {
+        let replay = ReplayProvider::new(cassette(), true);
+        assert_eq!(next_response(&replay, "edited"), None);
+        assert_eq!(next_response(&replay, "first"), Some("one".to_string()));
+    }
+}
@@ -0,0 +1,195 @@
+use dotenv::dotenv;
+use std::{
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::apis::llm_error::LlmError;
+
+// Caps on llm traffic shared by every agent and route, unlimited where None
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimits {
+    // Requests in flight at once
+    pub max_concurrent: Option<usize>,
+    pub requests_per_minute: Option<u64>,
+    // Prompt plus completion tokens
+    pub tokens_per_minute: Option<u64>,
+}
+
+impl RateLimits {
+    // LLM_MAX_CONCURRENT, LLM_RPM and LLM_TPM
+    pub fn from_env() -> Result<Self, LlmError> {
+        dotenv().ok();
+        let var = |name: &str| -> Result<Option<u64>, LlmError> {
+            match env::var(name) {
+                Ok(value) => value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|e| LlmError::Config(format!("{}: {}", name, e))),
+                Err(_) => Ok(None),
+            }
+        };
+
+        Ok(Self {
+            max_concurrent: var("LLM_MAX_CONCURRENT")?.map(|max| max as usize),
+            requests_per_minute: var("LLM_RPM")?,
+            tokens_per_minute: var("LLM_TPM")?,
+        })
+    }
+}
+
+// Refills `per_minute` units a minute up to a minute's worth. Reservations may overdraw it,
+// the caller then waits until the balance is back at zero, so waiting callers queue up
+// in the order they reserved.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    per_sec: f64,
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u64, now: Instant) -> Self {
+        Self {
+            capacity: per_minute as f64,
+            per_sec: per_minute as f64 / 60.0,
+            available: per_minute as f64,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed: f64 = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.available = (self.available + elapsed * self.per_sec).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    // How long to wait before using what was just reserved
+    fn reserve(&mut self, amount: f64, now: Instant) -> Duration {
+        self.refill(now);
+        self.available -= amount;
+        match self.available >= 0.0 || self.per_sec <= 0.0 {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64(-self.available / self.per_sec),
+        }
+    }
+
+    // Corrects an earlier reservation once the real amount is known
+    fn adjust(&mut self, difference: f64, now: Instant) {
+        self.refill(now);
+        self.available = (self.available - difference).min(self.capacity);
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    concurrency: Option<Semaphore>,
+    requests: Option<Mutex<TokenBucket>>,
+    tokens: Option<Mutex<TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        let now: Instant = Instant::now();
+        Self {
+            concurrency: limits.max_concurrent.map(|max| Semaphore::new(max.max(1))),
+            requests: limits
+                .requests_per_minute
+                .map(|rpm| Mutex::new(TokenBucket::new(rpm, now))),
+            tokens: limits
+                .tokens_per_minute
+                .map(|tpm| Mutex::new(TokenBucket::new(tpm, now))),
+        }
+    }
+
+    // Waits for room under every limit. The permit holds a concurrency slot until dropped.
+    pub async fn acquire(
+        &self,
+        estimated_tokens: u64,
+    ) -> Result<Option<SemaphorePermit<'_>>, LlmError> {
+        let now: Instant = Instant::now();
+        let mut wait: Duration = Duration::ZERO;
+        if let Some(requests) = &self.requests {
+            wait = wait.max(requests.lock().unwrap().reserve(1.0, now));
+        }
+        if let Some(tokens) = &self.tokens {
+            wait = wait.max(tokens.lock().unwrap().reserve(estimated_tokens as f64, now));
+        }
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+
+        match &self.concurrency {
+            Some(semaphore) => semaphore
+                .acquire()
+                .await
+                .map(Some)
+                .map_err(|e| LlmError::Config(format!("rate limiter: {}", e))),
+            None => Ok(None),
+        }
+    }
+
+    // Books the tokens a request really used in place of its estimate
+    pub fn settle(&self, estimated_tokens: u64, used_tokens: u64) {
+        if let Some(tokens) = &self.tokens {
+            let difference: f64 = used_tokens as f64 - estimated_tokens as f64;
+            tokens.lock().unwrap().adjust(difference, Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn tests_bucket_waits_for_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(60, start);
+
+        assert_eq!(bucket.reserve(60.0, start), Duration::ZERO);
+        // One a second comes back, the next two callers wait one and two seconds
+        assert_eq!(bucket.reserve(1.0, start), Duration::from_secs(1));
+        assert_eq!(bucket.reserve(1.0, start), Duration::from_secs(2));
+
+        // Never refills beyond a minute's worth
+        let later = start + Duration::from_secs(600);
+        assert_eq!(bucket.reserve(60.0, later), Duration::ZERO);
+        assert_eq!(bucket.reserve(30.0, later), Duration::from_secs(30));
+
+        // A request that used less than reserved gives the rest back
+        bucket.adjust(-30.0, later);
+        assert_eq!(bucket.reserve(0.0, later), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn tests_concurrency_limit_queues_requests() {
+        let limiter = Arc::new(RateLimiter::new(&RateLimits {
+            max_concurrent: Some(1),
+            ..Default::default()
+        }));
+
+        let permit = limiter.acquire(10).await.unwrap();
+        let waiting = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire(10).await.map(|permit| permit.is_some()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(permit);
+        assert!(waiting.await.unwrap().unwrap());
+        assert!(RateLimiter::new(&RateLimits::default())
+            .acquire(10)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
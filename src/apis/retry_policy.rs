@@ -0,0 +1,225 @@
+use dotenv::dotenv;
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::apis::llm_error::LlmError;
+
+// How often and how patiently failed llm calls are retried.
+// Only retryable errors (rate limits, 5xx, timeouts, network) are retried; a Retry-After
+// sent by the provider replaces the computed backoff, capped at max_delay as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Reads LLM_MAX_RETRIES, LLM_RETRY_BASE_MS, LLM_RETRY_MAX_MS and LLM_RETRY_JITTER
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let mut policy = Self::default();
+
+        // Values out of range for their type are ignored like unparsable ones
+        if let Some(max_retries) = var::<u32>("LLM_MAX_RETRIES") {
+            policy.max_retries = max_retries;
+        }
+        if let Some(ms) = var::<u64>("LLM_RETRY_BASE_MS") {
+            policy.base_delay = Duration::from_millis(ms);
+        }
+        if let Some(ms) = var::<u64>("LLM_RETRY_MAX_MS") {
+            policy.max_delay = Duration::from_millis(ms);
+        }
+        if let Ok(jitter) = env::var("LLM_RETRY_JITTER") {
+            policy.jitter = !matches!(jitter.trim(), "0" | "false" | "off");
+        }
+        policy
+    }
+
+    // Exponential backoff before retry number `retry` (0 based), capped at max_delay.
+    // With jitter the delay is drawn from [delay / 2, delay].
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp: f64 = self
+            .multiplier
+            .powi(i32::try_from(retry).unwrap_or(i32::MAX));
+        // Capped before it becomes a Duration, a long retry run overflows one
+        let delay: Duration = Duration::try_from_secs_f64(self.base_delay.as_secs_f64() * exp)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+
+        match self.jitter {
+            true => delay.mul_f64(0.5 + random_unit() / 2.0),
+            false => delay,
+        }
+    }
+
+    fn delay_for(&self, err: &LlmError, retry: u32) -> Duration {
+        match err.retry_after() {
+            Some(retry_after) => retry_after.min(self.max_delay),
+            None => self.backoff(retry),
+        }
+    }
+
+    // Runs the call until it succeeds, fails with a fatal error or runs out of retries.
+    // `on_retry` is told about each failure that will be retried and the wait before it.
+    pub async fn run<T, F, Fut>(
+        &self,
+        mut call: F,
+        mut on_retry: impl FnMut(&LlmError, u32, Duration),
+    ) -> Result<T, LlmError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, LlmError>>,
+    {
+        let mut retry: u32 = 0;
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(err) if !err.is_retryable() => return Err(err),
+                Err(err) if retry >= self.max_retries => {
+                    return Err(match retry {
+                        0 => err,
+                        _ => LlmError::Exhausted {
+                            attempts: retry + 1,
+                            last: Box::new(err),
+                        },
+                    })
+                }
+                Err(err) => {
+                    let delay: Duration = self.delay_for(&err, retry);
+                    retry += 1;
+                    on_retry(&err, retry, delay);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+fn var<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok()?.trim().parse().ok()
+}
+
+// Uniform value in [0, 1) from the randomly keyed std hasher, avoids pulling in an rng crate
+fn random_unit() -> f64 {
+    let bits: u64 = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn quick_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            multiplier: 2.0,
+            jitter: false,
+        }
+    }
+
+    #[test]
+    fn tests_backoff_grows_and_caps() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(30));
+        // Far past what a Duration holds
+        assert_eq!(policy.backoff(100), Duration::from_secs(30));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(30));
+
+        let jittered = RetryPolicy::default().backoff(1);
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn tests_retries_transient_errors() {
+        let calls = AtomicU32::new(0);
+        let res = quick_policy(3)
+            .run(
+                || async {
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err(LlmError::Timeout),
+                        1 => Err(LlmError::RateLimited {
+                            retry_after: Some(Duration::from_millis(1)),
+                            message: String::new(),
+                        }),
+                        _ => Ok("done"),
+                    }
+                },
+                |_, _, _| {},
+            )
+            .await;
+        assert_eq!(res.unwrap(), "done");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn tests_retry_after_is_capped() {
+        let policy = RetryPolicy::default();
+        let rate_limited = |secs: u64| LlmError::RateLimited {
+            retry_after: Some(Duration::from_secs(secs)),
+            message: String::new(),
+        };
+        assert_eq!(
+            policy.delay_for(&rate_limited(3), 0),
+            Duration::from_secs(3)
+        );
+        assert_eq!(policy.delay_for(&rate_limited(86_400), 0), policy.max_delay);
+    }
+
+    #[tokio::test]
+    async fn tests_fatal_errors_are_not_retried() {
+        let calls = AtomicU32::new(0);
+        let res: Result<(), LlmError> = quick_policy(3)
+            .run(
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(LlmError::Auth {
+                        status: 401,
+                        message: String::new(),
+                    })
+                },
+                |_, _, _| {},
+            )
+            .await;
+        assert!(matches!(res, Err(LlmError::Auth { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn tests_gives_up_after_max_retries() {
+        let res: Result<(), LlmError> = quick_policy(2)
+            .run(
+                || async { Err(LlmError::Network("down".to_string())) },
+                |_, _, _| {},
+            )
+            .await;
+        assert!(matches!(res, Err(LlmError::Exhausted { attempts: 3, .. })));
+    }
+}
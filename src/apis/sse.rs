@@ -0,0 +1,104 @@
+use crate::apis::llm_error::LlmError;
+
+// One server-sent event, `event` is only set when the server names it (Anthropic does, OpenAI not)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+// Turns the body of a text/event-stream response into events as the bytes arrive.
+// Only complete lines are decoded, so multi byte characters split across chunks survive.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events: Vec<SseEvent> = vec![];
+
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line: String = String::from_utf8_lossy(&line).to_string();
+            let line: &str = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take(),
+                        data: self.data.drain(..).collect::<Vec<String>>().join("\n"),
+                    });
+                }
+                self.event = None;
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value: &str = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                // Comments (": keep-alive"), id and retry are of no use here
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+// Reads the stream to the end, handing every event to `on_event` until it returns false
+pub async fn read_events(
+    mut res: reqwest::Response,
+    mut on_event: impl FnMut(SseEvent) -> Result<bool, LlmError>,
+) -> Result<(), LlmError> {
+    let mut decoder = SseDecoder::default();
+    while let Some(chunk) = res.chunk().await? {
+        for event in decoder.push(&chunk) {
+            if !on_event(event)? {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        let mut events = decoder.push(b"event: content_block_delta\ndata: {\"a\":");
+        assert!(events.is_empty());
+
+        events.extend(decoder.push(b"1}\r\n\r\n: keep-alive\n\ndata: [DONE]\n"));
+        events.extend(decoder.push(b"\n"));
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("content_block_delta".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "[DONE]".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tests_multibyte_characters_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        let bytes = "data: héllo\n\n".as_bytes();
+        let mut events = decoder.push(&bytes[..8]);
+        events.extend(decoder.push(&bytes[8..]));
+        assert_eq!(events[0].data, "héllo");
+    }
+}
@@ -0,0 +1,246 @@
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde_json::{json, Map, Value};
+
+// JSON schema an ai_function's answer has to follow, derived from the type it is decoded into.
+// Sent to providers with a structured output or tool mode, and checked before deserializing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSchema {
+    pub name: String,
+    pub schema: Value,
+}
+
+impl OutputSchema {
+    pub fn of<T: JsonSchema>(name: &str) -> Self {
+        // Inlined so the schema has no $ref / definitions and can be nested when wrapped
+        let settings = SchemaSettings::draft07().with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        });
+        let root = settings.into_generator().into_root_schema_for::<T>();
+        let mut schema: Value = serde_json::to_value(root).unwrap_or_else(|_| json!({}));
+        normalize(&mut schema);
+
+        Self {
+            name: name.to_string(),
+            schema,
+        }
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.schema.get("type") == Some(&json!("object"))
+    }
+
+    // OpenAI response formats and Anthropic tool inputs must be objects,
+    // other answers (Vec<String> ...) travel as {"value": ...}
+    pub fn object_schema(&self) -> Value {
+        match self.is_object() {
+            true => self.schema.clone(),
+            false => json!({
+                "type": "object",
+                "properties": { "value": self.schema },
+                "required": ["value"],
+            }),
+        }
+    }
+
+    // Undoes object_schema on the returned JSON. Anything that is not a wrapper is left alone
+    // so answers from providers without a structured mode pass through unchanged.
+    pub fn unwrap_value(&self, value: Value) -> Value {
+        match (self.is_object(), value) {
+            (false, Value::Object(mut map)) if map.len() == 1 && map.contains_key("value") => {
+                map.remove("value").unwrap_or(Value::Null)
+            }
+            (_, value) => value,
+        }
+    }
+
+    pub fn unwrap_content(&self, content: String) -> String {
+        match serde_json::from_str::<Value>(&content) {
+            Ok(value) => self.unwrap_value(value).to_string(),
+            Err(_) => content,
+        }
+    }
+
+    // Every mismatch with its path, e.g. `$[0].is_route_dynamic: expected string, got false`
+    pub fn validate(&self, value: &Value) -> Result<(), Vec<String>> {
+        let mut errors: Vec<String> = vec![];
+        validate_at(&self.schema, value, "$", &mut errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+// `true` (any value, e.g. serde_json::Value fields) is spelled {} which every provider accepts,
+// and titles and formats add nothing for the model
+fn normalize(schema: &mut Value) {
+    match schema {
+        Value::Bool(true) => *schema = json!({}),
+        Value::Object(map) => {
+            map.remove("title");
+            map.remove("format");
+            if let Some(Value::Object(properties)) = map.get_mut("properties") {
+                properties.values_mut().for_each(normalize);
+            }
+            if let Some(items) = map.get_mut("items") {
+                normalize(items);
+            }
+            if let Some(Value::Array(variants)) = map.get_mut("anyOf") {
+                variants.iter_mut().for_each(normalize);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema: &Map<String, Value> = match schema {
+        Value::Object(schema) => schema,
+        Value::Bool(false) => return errors.push(format!("{}: no value allowed", path)),
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| matches_type(name, value)) {
+            return errors.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                allowed.join(" or "),
+                value
+            ));
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            errors.push(format!("{}: {} is not one of {:?}", path, value, options));
+        }
+    }
+
+    if let (Some(minimum), Some(number)) = (
+        schema.get("minimum").and_then(Value::as_f64),
+        value.as_f64(),
+    ) {
+        if number < minimum {
+            errors.push(format!("{}: {} is below {}", path, number, minimum));
+        }
+    }
+
+    if let Some(Value::Array(variants)) = schema.get("anyOf") {
+        let any_valid: bool = variants.iter().any(|variant| {
+            let mut variant_errors: Vec<String> = vec![];
+            validate_at(variant, value, path, &mut variant_errors);
+            variant_errors.is_empty()
+        });
+        if !any_valid {
+            errors.push(format!("{}: matches none of the allowed shapes", path));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !fields.contains_key(name) {
+                        errors.push(format!("{}: missing field {}", path, name));
+                    }
+                }
+            }
+            if let Some(Value::Object(properties)) = schema.get("properties") {
+                for (name, property) in properties {
+                    if let Some(field) = fields.get(name) {
+                        validate_at(property, field, &format!("{}.{}", path, name), errors);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, index), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::agents::agent_traits::{ProjectScope, RouteObject};
+
+    #[test]
+    fn tests_schema_validates_decoded_types() {
+        let schema = OutputSchema::of::<ProjectScope>("print_project_scope");
+        assert!(schema.is_object());
+        assert!(schema
+            .validate(&json!({
+                "is_crud_required": true,
+                "is_user_login_and_logout": false,
+                "is_external_urls_required": false
+            }))
+            .is_ok());
+
+        let errors = schema
+            .validate(&json!({ "is_crud_required": "true", "is_user_login_and_logout": false }))
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("missing field is_external_urls_required"));
+        assert!(errors[1].starts_with("$.is_crud_required: expected boolean"));
+
+        let schema = OutputSchema::of::<Vec<RouteObject>>("print_rest_api_endpoints");
+        let route = json!({
+            "is_route_dynamic": "false",
+            "method": "get",
+            "request_body": null,
+            "response": { "id": "number" },
+            "route": "/health"
+        });
+        assert!(schema.validate(&json!([route])).is_ok());
+        let errors = schema.validate(&json!([{ "route": 1 }])).unwrap_err();
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("$[0].route: expected string")));
+    }
+
+    #[test]
+    fn tests_non_object_answers_are_wrapped() {
+        let schema = OutputSchema::of::<Vec<String>>("print_site_urls");
+        assert!(!schema.is_object());
+        assert_eq!(schema.object_schema()["required"], json!(["value"]));
+
+        let urls = json!(["https://api.frankfurter.app/latest"]);
+        assert_eq!(schema.unwrap_value(json!({ "value": urls })), urls);
+        assert_eq!(schema.unwrap_value(urls.clone()), urls);
+        assert_eq!(
+            schema.unwrap_content("not json".to_string()),
+            "not json".to_string()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use crate::apis::llm_error::LlmError;
+use crate::models::general::budget::BudgetExceeded;
+
+// Everything that can stop an agent, propagated up to ManagingAgent::execute_project
+#[derive(Debug)]
+pub enum AgentError {
+    // LLM call failed (after retries)
+    Provider(LlmError),
+    // LLM answered, but not with what the ai_function promised
+    Decode {
+        ai_function: String,
+        message: String,
+        response: String,
+    },
+    // Generated backend does not compile
+    Build {
+        attempts: u8,
+        errors: String,
+    },
+    // Generated backend compiled but could not be run or tested
+    Runtime(String),
+    // Missing or invalid settings such as TEMPLATE_PATH
+    Config(String),
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    // User declined to continue
+    UserAbort(String),
+    // A run or agent cap on tokens, cost, calls or time was reached
+    Budget(BudgetExceeded),
+}
+
+impl AgentError {
+    pub fn decode(ai_function: &str, err: impl fmt::Display, response: &str) -> Self {
+        Self::Decode {
+            ai_function: ai_function.to_string(),
+            message: err.to_string(),
+            response: response.to_string(),
+        }
+    }
+
+    // Raw model output or compiler errors behind the failure, for the end of run report
+    pub fn details(&self) -> Option<&str> {
+        match self {
+            Self::Decode { response, .. } => Some(response),
+            Self::Build { errors, .. } => Some(errors),
+            _ => None,
+        }
+    }
+
+    // Attaches what was being done to an io error
+    pub fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context: String = context.into();
+        move |source| Self::Io { context, source }
+    }
+}
+
+impl From<LlmError> for AgentError {
+    fn from(e: LlmError) -> Self {
+        Self::Provider(e)
+    }
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Provider(e) => write!(f, "{}", e),
+            Self::Decode {
+                ai_function,
+                message,
+                ..
+            } => write!(f, "could not decode {} response: {}", ai_function, message),
+            Self::Build { attempts, .. } => {
+                write!(
+                    f,
+                    "backend still fails to build after {} attempts",
+                    attempts
+                )
+            }
+            Self::Runtime(msg) => write!(f, "backend failed at runtime: {}", msg),
+            Self::Config(msg) => write!(f, "configuration error: {}", msg),
+            Self::Io { context, source } => write!(f, "{}: {}", context, source),
+            Self::UserAbort(msg) => write!(f, "stopped by user: {}", msg),
+            Self::Budget(exceeded) => write!(f, "{}", exceeded),
+        }
+    }
+}
+
+impl std::error::Error for AgentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Provider(e) => Some(e),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_error_messages() {
+        let err: AgentError = LlmError::Timeout.into();
+        assert_eq!(err.to_string(), "llm provider timed out");
+
+        let serde_err = serde_json::from_str::<Vec<String>>("[\"a\",]").unwrap_err();
+        let err = AgentError::decode("print_site_urls", serde_err, "[\"a\",]");
+        assert!(err
+            .to_string()
+            .starts_with("could not decode print_site_urls response"));
+        assert_eq!(err.details(), Some("[\"a\",]"));
+
+        let err =
+            AgentError::io("Failed to read code template")(std::io::ErrorKind::NotFound.into());
+        assert!(err.to_string().starts_with("Failed to read code template"));
+    }
+}
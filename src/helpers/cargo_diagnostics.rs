@@ -0,0 +1,315 @@
+// Errors of a `cargo build --message-format=json` run, parsed from the compiler messages.
+// rustc's machine-applicable suggestions can be applied to the crate without asking the model.
+
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::error::AgentError;
+
+// Unresolved imports, paths, types, names and crates, usually the cause of errors further down
+const ROOT_CAUSE_CODES: [&str; 5] = ["E0432", "E0433", "E0412", "E0425", "E0463"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub replacement: String,
+}
+
+// One rustc suggestion, its edits are only applied together
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuggestedFix {
+    pub message: String,
+    pub edits: Vec<Edit>,
+    pub machine_applicable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: Option<String>,
+    pub message: String,
+    // Primary span, None for errors without a location such as linker failures
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    // rustc's own text, with the code snippet
+    pub rendered: String,
+    pub fixes: Vec<SuggestedFix>,
+}
+
+// Code, message, file, line and column
+type ErrorKey = (Option<String>, String, Option<String>, usize, usize);
+
+impl Diagnostic {
+    fn key(&self) -> ErrorKey {
+        (
+            self.code.clone(),
+            self.message.clone(),
+            self.file.clone(),
+            self.line,
+            self.column,
+        )
+    }
+
+    fn is_root_cause(&self) -> bool {
+        self.code
+            .as_deref()
+            .is_some_and(|code| ROOT_CAUSE_CODES.contains(&code))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    pub success: bool,
+    pub errors: Vec<Diagnostic>,
+    // Failures before rustc runs, such as an invalid manifest, are only reported here
+    pub stderr: String,
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    code: Option<RustcCode>,
+    level: String,
+    #[serde(default)]
+    spans: Vec<RustcSpan>,
+    #[serde(default)]
+    children: Vec<RustcMessage>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+    suggested_replacement: Option<String>,
+    suggestion_applicability: Option<String>,
+}
+
+impl From<RustcMessage> for Diagnostic {
+    fn from(message: RustcMessage) -> Self {
+        let primary: Option<&RustcSpan> = message.spans.iter().find(|span| span.is_primary);
+        let fixes: Vec<SuggestedFix> = std::iter::once(&message)
+            .chain(message.children.iter())
+            .filter_map(suggested_fix)
+            .collect();
+        Self {
+            code: message.code.as_ref().map(|code| code.code.clone()),
+            file: primary.map(|span| span.file_name.clone()),
+            line: primary.map_or(0, |span| span.line_start),
+            column: primary.map_or(0, |span| span.column_start),
+            rendered: message
+                .rendered
+                .clone()
+                .unwrap_or_else(|| format!("error: {}\n", message.message)),
+            message: message.message,
+            fixes,
+        }
+    }
+}
+
+// The replacements suggested by a message, usually a help child of the error
+fn suggested_fix(message: &RustcMessage) -> Option<SuggestedFix> {
+    let spans: Vec<&RustcSpan> = message
+        .spans
+        .iter()
+        .filter(|span| span.suggested_replacement.is_some())
+        .collect();
+    if spans.is_empty() {
+        return None;
+    }
+    Some(SuggestedFix {
+        message: message.message.clone(),
+        machine_applicable: spans
+            .iter()
+            .all(|span| span.suggestion_applicability.as_deref() == Some("MachineApplicable")),
+        edits: spans
+            .into_iter()
+            .map(|span| Edit {
+                file: span.file_name.clone(),
+                byte_start: span.byte_start,
+                byte_end: span.byte_end,
+                replacement: span.suggested_replacement.clone().unwrap_or_default(),
+            })
+            .collect(),
+    })
+}
+
+pub fn cargo_build(crate_dir: &Path) -> Result<BuildOutput, AgentError> {
+    let output: std::process::Output = Command::new("cargo")
+        .args(["build", "--message-format=json"])
+        .current_dir(crate_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(AgentError::io("Failed to build backend application"))?;
+    Ok(BuildOutput {
+        success: output.status.success(),
+        errors: parse_diagnostics(&String::from_utf8_lossy(&output.stdout)),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+// Errors of the compiler messages, deduplicated, root causes first and then in file order.
+// Warnings and notes are left out.
+pub fn parse_diagnostics(stdout: &str) -> Vec<Diagnostic> {
+    let mut seen: BTreeSet<ErrorKey> = BTreeSet::new();
+    let mut errors: Vec<Diagnostic> = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CargoMessage>(line).ok())
+        .filter(|cargo_message| cargo_message.reason == "compiler-message")
+        .filter_map(|cargo_message| cargo_message.message)
+        .filter(|message| message.level == "error" && !message.message.starts_with("aborting"))
+        .map(Diagnostic::from)
+        // The same error comes once for every target compiling the file
+        .filter(|error| seen.insert(error.key()))
+        .collect();
+    errors.sort_by_key(|error| {
+        (
+            !error.is_root_cause(),
+            error.file.clone(),
+            error.line,
+            error.column,
+        )
+    });
+    errors
+}
+
+// rustc's text of the first max_errors errors, and how many were left out
+pub fn format_errors(errors: &[Diagnostic], max_errors: usize) -> (String, usize) {
+    let omitted: usize = errors.len().saturating_sub(max_errors);
+    let mut text: String = errors
+        .iter()
+        .take(max_errors)
+        .map(|error| error.rendered.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n");
+    if omitted > 0 {
+        text.push_str(&format!("\n... and {} more errors\n", omitted));
+    }
+    (text, omitted)
+}
+
+// Applies the machine-applicable fixes of the errors to the crate's files. A fix overlapping
+// one already taken waits for the next build. Returns the changed files.
+pub fn apply_fixes(
+    crate_dir: &Path,
+    errors: &[Diagnostic],
+) -> Result<BTreeSet<String>, AgentError> {
+    let mut taken: Vec<&Edit> = vec![];
+    for fix in errors
+        .iter()
+        .flat_map(|error| &error.fixes)
+        .filter(|fix| fix.machine_applicable)
+    {
+        let applicable: bool = fix.edits.iter().all(|edit| {
+            inside_crate(&edit.file) && !taken.iter().any(|other| overlaps(edit, other))
+        });
+        if applicable {
+            taken.extend(&fix.edits);
+        }
+    }
+
+    let files: BTreeSet<String> = taken.iter().map(|edit| edit.file.clone()).collect();
+    for file in &files {
+        let path: PathBuf = crate_dir.join(file);
+        let mut contents: String = fs::read_to_string(&path)
+            .map_err(AgentError::io(format!("Failed to read {}", path.display())))?;
+        let mut edits: Vec<&&Edit> = taken.iter().filter(|edit| &edit.file == file).collect();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.byte_start));
+        // From the end of the file, so earlier offsets stay valid
+        for edit in edits {
+            if contents.get(edit.byte_start..edit.byte_end).is_some() {
+                contents.replace_range(edit.byte_start..edit.byte_end, &edit.replacement);
+            }
+        }
+        fs::write(&path, contents).map_err(AgentError::io(format!(
+            "Failed to write {}",
+            path.display()
+        )))?;
+    }
+    Ok(files)
+}
+
+// Spans in dependencies are absolute paths and left alone
+fn inside_crate(file: &str) -> bool {
+    Path::new(file)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+// Touching edits count too, two insertions at one place would clash
+fn overlaps(edit: &Edit, other: &Edit) -> bool {
+    edit.file == other.file
+        && edit.byte_start <= other.byte_end
+        && other.byte_start <= edit.byte_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_support::fixture_path;
+    use std::env;
+
+    #[test]
+    fn tests_errors_are_parsed_ranked_and_fixed() {
+        let fixtures = fixture_path("cargo_diagnostics");
+        let stdout = fs::read_to_string(fixtures.join("build.jsonl")).unwrap();
+
+        let errors = parse_diagnostics(&stdout);
+        let codes: Vec<Option<&str>> = errors.iter().map(|e| e.code.as_deref()).collect();
+        // The duplicate of E0425, the warning and the notes are gone
+        assert_eq!(
+            codes,
+            vec![Some("E0432"), Some("E0425"), Some("E0308"), Some("E0308")]
+        );
+        assert_eq!(errors[1].file.as_deref(), Some("src/main.rs"));
+        assert_eq!((errors[1].line, errors[1].column), (13, 32));
+        assert!(errors[1]
+            .rendered
+            .contains("println!(\"{} {} {}\", a, n, z);"));
+        assert!(errors[2].fixes[0].machine_applicable);
+        assert!(!errors[1].fixes.iter().any(|fix| fix.machine_applicable));
+
+        let (text, omitted) = format_errors(&errors, 2);
+        assert_eq!(omitted, 2);
+        assert!(text.starts_with("error[E0432]: unresolved import `serde`"));
+        assert!(!text.contains("E0308"));
+        assert!(text.ends_with("... and 2 more errors\n"));
+
+        let crate_dir = env::temp_dir().join(format!("auto_gippity_fixes_{}", std::process::id()));
+        fs::create_dir_all(crate_dir.join("src")).unwrap();
+        fs::copy(fixtures.join("main.rs"), crate_dir.join("src/main.rs")).unwrap();
+
+        let changed = apply_fixes(&crate_dir, &errors).unwrap();
+        assert_eq!(changed, BTreeSet::from(["src/main.rs".to_string()]));
+        let fixed = fs::read_to_string(crate_dir.join("src/main.rs")).unwrap();
+        assert!(fixed.contains("let a = takes(&s.clone());"));
+        assert!(fixed.contains("let n: u64 = v.len().try_into().unwrap();"));
+        // What only the model can fix is left as it was
+        assert!(fixed.contains("use serde::Serialize;"));
+        assert!(fixed.contains("a, n, z);"));
+        fs::remove_dir_all(crate_dir).unwrap();
+    }
+}
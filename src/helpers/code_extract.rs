@@ -0,0 +1,366 @@
+// Pulls source files out of a model answer written as markdown.
+// Code is taken from fenced blocks tagged with the language (or untagged ones if none are),
+// split into files on `// file: src/foo.rs` header lines. Everything without a header
+// belongs to src/main.rs, multiple blocks for the same file are joined in order.
+// Blocks in another language are only taken when they start with a header, such as
+// `# file: Cargo.toml` in a toml block.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const MAIN_FILE: &str = "src/main.rs";
+
+const FILE_HEADER: &str = "// file:";
+const CONFIG_FILE_HEADER: &str = "# file:";
+
+// Files other than Rust sources the model may write, anywhere in the crate
+const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "json", "yaml", "yml"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeFile {
+    pub path: String,
+    pub contents: String,
+}
+
+// The generated files of the backend crate, by path relative to the crate
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CodeMap(BTreeMap<String, String>);
+
+impl CodeMap {
+    // Files of a later answer replace those with the same path, the others are kept
+    pub fn merge(&mut self, files: Vec<CodeFile>) {
+        for file in files {
+            self.0.insert(file.path, file.contents);
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.0.get(path).map(String::as_str)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    pub fn files(&self) -> Vec<CodeFile> {
+        self.0
+            .iter()
+            .map(|(path, contents)| CodeFile {
+                path: path.clone(),
+                contents: contents.clone(),
+            })
+            .collect()
+    }
+
+    // Only the given files, all of them when none of the paths are in the map
+    pub fn only(&self, paths: &BTreeSet<String>) -> CodeMap {
+        let picked: BTreeMap<String, String> = self
+            .0
+            .iter()
+            .filter(|(path, _)| paths.contains(*path))
+            .map(|(path, contents)| (path.clone(), contents.clone()))
+            .collect();
+        match picked.is_empty() {
+            true => self.clone(),
+            false => CodeMap(picked),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        render_files(&self.files())
+    }
+}
+
+impl From<Vec<CodeFile>> for CodeMap {
+    fn from(files: Vec<CodeFile>) -> Self {
+        let mut code: CodeMap = CodeMap::default();
+        code.merge(files);
+        code
+    }
+}
+
+struct CodeBlock<'a> {
+    tag: &'a str,
+    lines: Vec<&'a str>,
+}
+
+// Err is the reason the answer was rejected, phrased to be sent back to the model
+pub fn extract_code(response: &str, language: &str) -> Result<Vec<CodeFile>, String> {
+    extract_files(response, language, MAIN_FILE)
+}
+
+// Like extract_code, with code before any header belonging to default_path,
+// e.g. the only file a fix was asked for
+pub fn extract_files(
+    response: &str,
+    language: &str,
+    default_path: &str,
+) -> Result<Vec<CodeFile>, String> {
+    let blocks: Vec<CodeBlock> = fenced_blocks(response);
+
+    let code: Vec<Vec<&str>> = if blocks.is_empty() {
+        // No fences: the answer is the code, minus any prose before it
+        let lines: Vec<&str> = response
+            .lines()
+            .skip_while(|line| !looks_like_code(line))
+            .collect();
+        match lines.is_empty() {
+            true => vec![],
+            false => vec![lines],
+        }
+    } else {
+        let tagged: bool = blocks
+            .iter()
+            .any(|block| matches_language(block.tag, language));
+        blocks
+            .into_iter()
+            .filter(|block| match tagged {
+                true => matches_language(block.tag, language) || starts_with_header(block),
+                false => block.tag.is_empty() || starts_with_header(block),
+            })
+            .map(|block| block.lines)
+            .collect()
+    };
+
+    let mut files: Vec<CodeFile> = vec![];
+    for lines in code {
+        split_files(&lines, default_path, &mut files)?;
+    }
+    files.retain(|file| !file.contents.trim().is_empty());
+
+    match files.is_empty() {
+        true => Err(format!(
+            "the answer contains no {} code, print the complete code in a ```{} block",
+            language, language
+        )),
+        false => Ok(files),
+    }
+}
+
+// Inverse of extract_code, so the code can be kept as one string and extracted again
+pub fn render_files(files: &[CodeFile]) -> String {
+    match files {
+        [file] if file.path == MAIN_FILE => file.contents.clone(),
+        files => files
+            .iter()
+            .map(|file| {
+                format!(
+                    "{} {}\n{}",
+                    header_for(&file.path),
+                    file.path,
+                    file.contents
+                )
+            })
+            .collect::<Vec<String>>()
+            .concat(),
+    }
+}
+
+fn fenced_blocks(response: &str) -> Vec<CodeBlock<'_>> {
+    let mut blocks: Vec<CodeBlock> = vec![];
+    let mut open: Option<CodeBlock> = None;
+
+    for line in response.lines() {
+        let trimmed: &str = line.trim_start();
+        match (&mut open, trimmed.strip_prefix("```")) {
+            (None, Some(info)) => {
+                let tag: &str = info.split_whitespace().next().unwrap_or("");
+                open = Some(CodeBlock { tag, lines: vec![] });
+            }
+            (Some(_), Some(rest)) if rest.trim().is_empty() => {
+                blocks.extend(open.take());
+            }
+            (Some(block), _) => block.lines.push(line),
+            (None, None) => {}
+        }
+    }
+    // An answer cut off mid block still has its code
+    blocks.extend(open);
+    blocks
+}
+
+fn starts_with_header(block: &CodeBlock) -> bool {
+    block
+        .lines
+        .iter()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| file_header(line).is_some())
+}
+
+fn file_header(line: &str) -> Option<&str> {
+    let line: &str = line.trim();
+    line.strip_prefix(FILE_HEADER)
+        .or_else(|| line.strip_prefix(CONFIG_FILE_HEADER))
+}
+
+// `//` is no comment in toml or yaml
+fn header_for(path: &str) -> &'static str {
+    match path.ends_with(".rs") || path.ends_with(".json") {
+        true => FILE_HEADER,
+        false => CONFIG_FILE_HEADER,
+    }
+}
+
+fn matches_language(tag: &str, language: &str) -> bool {
+    let tag: String = tag.to_lowercase();
+    tag == language || (language == "rust" && tag == "rs")
+}
+
+fn looks_like_code(line: &str) -> bool {
+    const STARTS: [&str; 10] = [
+        "use ", "fn ", "pub ", "mod ", "struct ", "enum ", "impl ", "async ", "#[", "//",
+    ];
+    let line: &str = line.trim_start();
+    STARTS.iter().any(|start| line.starts_with(start)) || file_header(line).is_some()
+}
+
+fn split_files(
+    lines: &[&str],
+    default_path: &str,
+    files: &mut Vec<CodeFile>,
+) -> Result<(), String> {
+    let mut path: String = default_path.to_string();
+    let mut contents: String = String::new();
+
+    for line in lines {
+        match file_header(line) {
+            Some(header) => {
+                push_file(files, &path, contents);
+                path = checked_path(header.trim())?;
+                contents = String::new();
+            }
+            None => {
+                contents.push_str(line);
+                contents.push('\n');
+            }
+        }
+    }
+    push_file(files, &path, contents);
+    Ok(())
+}
+
+fn push_file(files: &mut Vec<CodeFile>, path: &str, contents: String) {
+    if contents.trim().is_empty() {
+        return;
+    }
+    match files.iter_mut().find(|file| file.path == path) {
+        Some(file) => {
+            file.contents.push('\n');
+            file.contents.push_str(&contents);
+        }
+        None => files.push(CodeFile {
+            path: path.to_string(),
+            contents,
+        }),
+    }
+}
+
+// Generated files have to stay inside the backend crate: Rust sources in its src directory,
+// config files such as Cargo.toml anywhere but the build output
+fn checked_path(path: &str) -> Result<String, String> {
+    let path: &str = path.trim_start_matches("./");
+    let path: String = match path.ends_with(".rs") && !path.starts_with("src/") {
+        true => format!("src/{}", path),
+        false => path.to_string(),
+    };
+    let escapes: bool =
+        path.split('/').any(|part| part == ".." || part.is_empty()) || path.starts_with("target/");
+    let extension: &str = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+    let allowed: bool = extension == "rs" || CONFIG_EXTENSIONS.contains(&extension);
+    match escapes || !allowed {
+        true => Err(format!(
+            "{} is not a valid file path, use paths like src/routes.rs or Cargo.toml",
+            path
+        )),
+        false => Ok(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_extracts_code_around_prose() {
+        let response = "Here is the server:\n```rust\nfn main() {}\n```\nIt listens on port 8080.\n```toml\n[dependencies]\n```";
+        let files = extract_code(response, "rust").unwrap();
+        assert_eq!(
+            files,
+            vec![CodeFile {
+                path: MAIN_FILE.to_string(),
+                contents: "fn main() {}\n".to_string()
+            }]
+        );
+
+        let files = extract_code("Sure.\nuse std::fs;\nfn main() {}", "rust").unwrap();
+        assert_eq!(files[0].contents, "use std::fs;\nfn main() {}\n");
+        assert_eq!(render_files(&files), "use std::fs;\nfn main() {}\n");
+    }
+
+    #[test]
+    fn tests_splits_multi_file_answers() {
+        let response = "```rust\n// file: src/main.rs\nmod routes;\nfn main() {}\n```\n\n```rs\n// file: routes.rs\npub fn health() {}\n```";
+        let files = extract_code(response, "rust").unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].path, "src/routes.rs");
+        assert_eq!(files[1].contents, "pub fn health() {}\n");
+        assert_eq!(extract_code(&render_files(&files), "rust").unwrap(), files);
+
+        let err = extract_code("```rust\n// file: ../build.rs\nfn main() {}\n```", "rust");
+        assert!(err.unwrap_err().contains("not a valid file path"));
+        let err = extract_code("```rust\n// file: build.sh\nrm -rf /\n```", "rust");
+        assert!(err.unwrap_err().contains("not a valid file path"));
+    }
+
+    #[test]
+    fn tests_code_map_keeps_config_files_and_merges_fixes() {
+        let response = "```rust\n// file: src/main.rs\nmod routes;\nfn main() {}\n```\n\
+```rust\n// file: src/routes.rs\npub fn health() {}\n```\n\
+```toml\n# file: Cargo.toml\n[package]\nname = \"backend\"\n```\n\
+```json\n// file: config/settings.json\n{\"port\": 8080}\n```";
+        let mut code = CodeMap::from(extract_code(response, "rust").unwrap());
+        assert_eq!(
+            code.paths().collect::<Vec<_>>(),
+            vec![
+                "Cargo.toml",
+                "config/settings.json",
+                "src/main.rs",
+                "src/routes.rs"
+            ]
+        );
+        assert_eq!(
+            code.get("Cargo.toml"),
+            Some("[package]\nname = \"backend\"\n")
+        );
+        assert!(code.render().contains("# file: Cargo.toml\n[package]"));
+        assert_eq!(
+            CodeMap::from(extract_code(&code.render(), "rust").unwrap()),
+            code
+        );
+
+        // A fix asked for one file may answer without a header
+        let broken = code.only(&BTreeSet::from(["src/routes.rs".to_string()]));
+        assert_eq!(broken.paths().collect::<Vec<_>>(), vec!["src/routes.rs"]);
+        let fixed = extract_files(
+            "```rust\npub fn health() -> u16 { 200 }\n```",
+            "rust",
+            "src/routes.rs",
+        )
+        .unwrap();
+        code.merge(fixed);
+        assert_eq!(
+            code.get("src/routes.rs"),
+            Some("pub fn health() -> u16 { 200 }\n")
+        );
+        assert_eq!(code.get("src/main.rs"), Some("mod routes;\nfn main() {}\n"));
+        assert_eq!(code.only(&BTreeSet::new()), code);
+    }
+
+    #[test]
+    fn tests_rejects_answers_without_code() {
+        let err = extract_code("I cannot write this server.", "rust").unwrap_err();
+        assert!(err.contains("no rust code"));
+        assert!(extract_code("```rust\n```", "rust").is_err());
+    }
+}
@@ -1,12 +1,32 @@
 use reqwest::Client;
+use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
-use std::env;
+use std::{env, time::Instant};
 
-use crate::apis::call_request::call_gpt;
-use crate::helpers::command_line::PrintCommand;
-use crate::models::general::llm::Message;
-use std::fs;
-use std::io::Write;
+use crate::apis::call_request::{call_gpt, call_gpt_stream, call_gpt_structured};
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::provider_for;
+use crate::apis::retry_policy::RetryPolicy;
+use crate::apis::structured_output::OutputSchema;
+use crate::error::AgentError;
+use crate::helpers::code_extract::CodeMap;
+use crate::helpers::command_line::{PrintCommand, StreamPrinter};
+use crate::helpers::json_repair::repair_json;
+use crate::helpers::prompt_library::prompt_library;
+use crate::helpers::prompt_packing::{count_tokens, prompt_room};
+use crate::helpers::redact::redact;
+use crate::models::agent_basic::memory::{remember, MemoryWindow};
+use crate::models::agents::agent_traits::BackendFramework;
+use crate::models::general::journal::{record_exchange, unix_millis, JournalEntry};
+use crate::models::general::llm::{LlmResponse, Message};
+use crate::models::general::prompt_eval::{record_first_answer, FirstAnswer};
+use crate::models::general::starter_templates::StarterTemplate;
+use crate::models::general::usage::{check_budget, record_usage};
+use crate::models::general::workspace::isolate_manifest;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub fn extend_ai_function(ai_func: fn(&str) -> &'static str, func_input: &str) -> Message {
     let ai_function_str = ai_func(func_input);
@@ -22,36 +42,304 @@ pub fn extend_ai_function(ai_func: fn(&str) -> &'static str, func_input: &str) -
     }
 }
 
+// The prompt named after agent_operation in the prompt library (LLM_PROMPTS_DIR), otherwise
+// the compiled-in ai_function
+pub fn function_message(
+    agent_operation: &str,
+    function_pass: for<'a> fn(&'a str) -> &'static str,
+    msg_context: &str,
+) -> Result<Message, AgentError> {
+    prompt_library()?.message(agent_operation, function_pass, msg_context)
+}
+
+// How the completion is asked for and shown
+#[derive(Clone, Copy)]
+enum ResponseMode<'a> {
+    Text,
+    // Printed while it is generated
+    Streamed,
+    // Constrained to a JSON schema where the provider supports it
+    Structured(&'a OutputSchema),
+}
+
+// The agent's earlier turns are sent along (see MemoryWindow) and this turn is recorded
+// in its memory, as with every request below
 pub async fn ai_task_request(
+    memory: &mut Vec<Message>,
+    msg_context: String,
+    agent_position: &str,
+    agent_operation: &str,
+    function_pass: for<'a> fn(&'a str) -> &'static str,
+) -> Result<String, AgentError> {
+    let func_msg: Message = function_message(agent_operation, function_pass, &msg_context)?;
+    let messages: Vec<Message> = with_memory(memory, &func_msg, agent_operation)?;
+
+    let llm_response: String = request_completion(
+        messages,
+        agent_position,
+        agent_operation,
+        ResponseMode::Text,
+    )
+    .await?;
+    remember_turn(memory, &func_msg, &llm_response);
+    Ok(llm_response)
+}
+
+// For long completions such as code: the text is printed while it is generated
+// (LLM_STREAM=0 turns streaming off)
+pub async fn ai_task_request_streamed(
+    memory: &mut Vec<Message>,
     msg_context: String,
     agent_position: &str,
     agent_operation: &str,
     function_pass: for<'a> fn(&'a str) -> &'static str,
-) -> String {
-    let func_msg = extend_ai_function(function_pass, &msg_context);
+) -> Result<String, AgentError> {
+    let func_msg: Message = function_message(agent_operation, function_pass, &msg_context)?;
+    let messages: Vec<Message> = with_memory(memory, &func_msg, agent_operation)?;
+
+    let llm_response: String =
+        request_completion(messages, agent_position, agent_operation, streamed_mode()).await?;
+    remember_turn(memory, &func_msg, &llm_response);
+    Ok(llm_response)
+}
+
+// The earlier turns that fit the ai_function's model, then the request
+fn with_memory(
+    memory: &[Message],
+    func_msg: &Message,
+    agent_operation: &str,
+) -> Result<Vec<Message>, AgentError> {
+    let model: String = provider_for(agent_operation)?.model().to_string();
+    // Whatever packed the request, the turns only get the room it left
+    let room: usize = prompt_room(&model).saturating_sub(count_tokens(&model, &func_msg.content));
+    let mut messages: Vec<Message> = MemoryWindow::for_model(&model)
+        .within(room)
+        .context(&model, memory);
+    // After earlier turns the request has to be the latest user turn
+    messages.push(match messages.is_empty() {
+        true => func_msg.clone(),
+        false => Message {
+            role: "user".to_string(),
+            content: func_msg.content.clone(),
+        },
+    });
+    Ok(messages)
+}
+
+// The answer is redacted already, memory ends up in debug output
+fn remember_turn(memory: &mut Vec<Message>, func_msg: &Message, answer: &str) {
+    remember(memory, &redact(&func_msg.content), answer);
+}
+
+fn streamed_mode() -> ResponseMode<'static> {
+    let stream_off: bool =
+        env::var("LLM_STREAM").is_ok_and(|value| matches!(value.trim(), "0" | "false" | "off"));
+    match stream_off {
+        true => ResponseMode::Text,
+        false => ResponseMode::Streamed,
+    }
+}
+
+async fn request_completion(
+    messages: Vec<Message>,
+    agent_position: &str,
+    agent_operation: &str,
+    mode: ResponseMode<'_>,
+) -> Result<String, AgentError> {
+    // Refuse the call once the run or this agent is out of budget
+    check_budget(agent_position)?;
+
     PrintCommand::AICall.print_agent_message(agent_position, agent_operation);
-    // LLM Response
-    let llm_response_res = call_gpt(vec![func_msg.clone()]).await;
-    match llm_response_res {
-        Ok(llm_res) => llm_res,
-        Err(_) => call_gpt(vec![func_msg])
-            .await
-            .expect("Failed twice to call gtp"),
+    // Secrets never reach the provider, and none it echoes back reach the journal or disk
+    let messages: Vec<Message> = messages
+        .into_iter()
+        .map(|msg| Message {
+            content: redact(&msg.content),
+            ..msg
+        })
+        .collect();
+    let streamed: bool = matches!(mode, ResponseMode::Streamed);
+    let stream_printer: StreamPrinter = StreamPrinter::default();
+    let started_at: u64 = unix_millis();
+    let started: Instant = Instant::now();
+    let mut retries: u32 = 0;
+    // LLM Response, rate limits and transient failures are retried with backoff
+    let result: Result<LlmResponse, LlmError> = RetryPolicy::from_env()
+        .run(
+            || async {
+                let messages: Vec<Message> = messages.clone();
+                match mode {
+                    ResponseMode::Text => call_gpt(agent_operation, messages).await,
+                    ResponseMode::Streamed => {
+                        let on_chunk = |chunk: &str| stream_printer.print_chunk(chunk);
+                        call_gpt_stream(agent_operation, messages, &on_chunk).await
+                    }
+                    ResponseMode::Structured(schema) => {
+                        call_gpt_structured(agent_operation, messages, schema).await
+                    }
+                }
+            },
+            |err, retry, delay| {
+                retries = retry;
+                if streamed {
+                    // End the partly streamed answer
+                    stream_printer.finish();
+                    println!();
+                }
+                let retry_msg: String =
+                    format!("{}. Retry {} in {:.1}s", err, retry, delay.as_secs_f32());
+                PrintCommand::Issue.print_agent_message(agent_position, retry_msg.as_str());
+            },
+        )
+        .await;
+    if streamed {
+        stream_printer.finish();
+        if result.is_ok() {
+            println!();
+        }
+    }
+    let result: Result<LlmResponse, LlmError> = result.map(|mut response| {
+        response.content = redact(&response.content);
+        response
+    });
+
+    // Failed exchanges are journaled too
+    let entry: JournalEntry = JournalEntry {
+        started_at,
+        agent: agent_position.to_string(),
+        ai_function: agent_operation.to_string(),
+        prompt_version: prompt_library()
+            .and_then(|library| library.version(agent_operation))
+            .unwrap_or_default(),
+        provider: provider_for(agent_operation)
+            .map(|provider| provider.name().to_string())
+            .unwrap_or_default(),
+        model: result
+            .as_ref()
+            .map(|response| response.model.clone())
+            .unwrap_or_default(),
+        messages,
+        response: result
+            .as_ref()
+            .ok()
+            .map(|response| response.content.clone()),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        latency_ms: started.elapsed().as_millis() as u64,
+        usage: result.as_ref().ok().and_then(|response| response.usage),
+        retries,
+        cached: result.as_ref().is_ok_and(|response| response.cached),
+    };
+    if let Err(e) = record_exchange(&entry) {
+        PrintCommand::Issue.print_agent_message(agent_position, &e.to_string());
     }
+    let llm_response: LlmResponse = result?;
+
+    if llm_response.cached {
+        PrintCommand::AICall.print_agent_message(agent_position, "Answered from response cache");
+    }
+    // Tokens are booked on the calling agent and ai_function
+    record_usage(agent_position, agent_operation, &llm_response);
+    Ok(llm_response.content)
 }
 
-pub async fn ai_task_request_decoded<T: DeserializeOwned>(
+// Asks for JSON matching the schema of T and checks the answer against it before decoding.
+// An answer that does not decode is first cleaned up locally, then sent back to the model
+// with the error, at most LLM_DECODE_REPAIR_ATTEMPTS times (default 2).
+pub async fn ai_task_request_decoded<T: DeserializeOwned + JsonSchema>(
+    memory: &mut Vec<Message>,
     msg_context: String,
     agent_position: &str,
     agent_operation: &str,
     function_pass: for<'a> fn(&'a str) -> &'static str,
-) -> T {
-    let llm_response =
-        ai_task_request(msg_context, agent_position, agent_operation, function_pass).await;
-    let decoded_message: T = serde_json::from_str(llm_response.as_str())
-        .expect("Failed to decode ai response from serde_json");
+) -> Result<T, AgentError> {
+    let schema: OutputSchema = OutputSchema::of::<T>(agent_operation);
+    let func_msg: Message = function_message(agent_operation, function_pass, &msg_context)?;
+    let max_repairs: u32 = decode_repair_attempts();
+    let request: Vec<Message> = with_memory(memory, &func_msg, agent_operation)?;
+
+    let mut llm_response: String = request_completion(
+        request.clone(),
+        agent_position,
+        agent_operation,
+        ResponseMode::Structured(&schema),
+    )
+    .await?;
+    record_first_answer(FirstAnswer {
+        valid_json: serde_json::from_str::<serde_json::Value>(&llm_response).is_ok(),
+        schema_ok: decode_answer::<T>(&schema, &llm_response).is_ok(),
+    });
+
+    let mut repair: u32 = 0;
+    loop {
+        let error: String = match decode_answer::<T>(&schema, &llm_response) {
+            Ok(decoded_message) => {
+                remember_turn(memory, &func_msg, &llm_response);
+                return Ok(decoded_message);
+            }
+            Err(error) => error,
+        };
+
+        let repaired: String = repair_json(&llm_response);
+        if repaired != llm_response {
+            if let Ok(decoded_message) = decode_answer::<T>(&schema, &repaired) {
+                PrintCommand::Issue
+                    .print_agent_message(agent_position, "Repaired malformed JSON locally");
+                remember_turn(memory, &func_msg, &repaired);
+                return Ok(decoded_message);
+            }
+        }
+
+        if repair >= max_repairs {
+            let message: String = format!("{} (after {} repair attempts)", error, repair);
+            return Err(AgentError::decode(agent_operation, message, &llm_response));
+        }
+        repair += 1;
+        let repair_msg: String = format!(
+            "Could not decode answer: {}. Asking for corrected JSON ({} of {})",
+            error, repair, max_repairs
+        );
+        PrintCommand::Issue.print_agent_message(agent_position, repair_msg.as_str());
+
+        // Same function call, with the bad answer and what is wrong with it
+        let mut messages: Vec<Message> = request.clone();
+        messages.extend([
+            Message {
+                role: "assistant".to_string(),
+                content: llm_response,
+            },
+            Message {
+                role: "user".to_string(),
+                content: format!(
+                    "That output could not be decoded: {}. \
+                    Print ONLY the corrected JSON. No code fences. No commentary.",
+                    error
+                ),
+            },
+        ]);
+        llm_response = request_completion(
+            messages,
+            agent_position,
+            agent_operation,
+            ResponseMode::Structured(&schema),
+        )
+        .await?;
+    }
+}
 
-    decoded_message
+fn decode_repair_attempts() -> u32 {
+    env::var("LLM_DECODE_REPAIR_ATTEMPTS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(2)
+}
+
+// Parse, schema check and deserialize, any failure as a message for the model
+fn decode_answer<T: DeserializeOwned>(schema: &OutputSchema, answer: &str) -> Result<T, String> {
+    let value: serde_json::Value = serde_json::from_str(answer).map_err(|e| e.to_string())?;
+    schema
+        .validate(&value)
+        .map_err(|errors| errors.join("; "))?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
 }
 
 pub async fn check_status_code(client: &Client, url: &str) -> Result<u16, reqwest::Error> {
@@ -59,33 +347,66 @@ pub async fn check_status_code(client: &Client, url: &str) -> Result<u16, reqwes
     Ok(response.status().as_u16())
 }
 
+pub fn env_path(var: &str) -> Result<String, AgentError> {
+    env::var(var).map_err(|_| AgentError::Config(format!("{} not set", var)))
+}
+
 // Get code template
-pub fn read_code_template_contents() -> String {
-    let path = env::var("TEMPLATE_PATH").expect("Code template path not found");
+// From TEMPLATE_PATH, otherwise the framework's built-in starter template
+pub fn read_code_template_contents(framework: BackendFramework) -> Result<String, AgentError> {
+    let Ok(path) = env_path("TEMPLATE_PATH") else {
+        let starter = StarterTemplate::for_framework(framework)?;
+        return Ok(starter.code_template().to_string());
+    };
     let template_path = format!("{}/_code_template.rs", path);
-    fs::read_to_string(template_path).expect("Failed to read code template")
+    fs::read_to_string(&template_path).map_err(AgentError::io(format!(
+        "Failed to read code template {}",
+        template_path
+    )))
 }
 
-pub fn read_exec_main_contents() -> String {
-    let path = env::var("TEMPLATE_PATH").expect("Code template path not found");
-    let template_path = format!("{}/main.rs", path);
-    fs::read_to_string(template_path).expect("Failed to read code template")
+pub fn read_exec_main_contents(workspace: &Path) -> Result<String, AgentError> {
+    let main_path: PathBuf = workspace.join("src").join("main.rs");
+    fs::read_to_string(&main_path).map_err(AgentError::io(format!(
+        "Failed to read backend code {}",
+        main_path.display()
+    )))
 }
-// Save new backend code
-pub fn save_backend_code(contents: &str) {
-    let path = env::var("TEMPLATE_PATH").expect("Code template path not found");
-    let main_path = format!("{}/main.rs", path);
-    fs::write(main_path, contents.as_bytes());
+// Save the whole code map into a backend crate, paths are relative to the crate
+pub fn save_backend_code(workspace: &Path, code: &CodeMap) -> Result<(), AgentError> {
+    for file in code.files() {
+        let file_path: PathBuf = workspace.join(&file.path);
+        if let Some(dir) = file_path.parent() {
+            fs::create_dir_all(dir).map_err(AgentError::io(format!(
+                "Failed to create directory {}",
+                dir.display()
+            )))?;
+        }
+        fs::write(&file_path, file.contents.as_bytes()).map_err(AgentError::io(format!(
+            "Failed to save backend code {}",
+            file_path.display()
+        )))?;
+    }
+    // A manifest from the model has to stay out of any surrounding Cargo workspace too
+    if code.get("Cargo.toml").is_some() {
+        isolate_manifest(workspace)?;
+    }
+    Ok(())
 }
 // Save JSON API Endpoint Schema
-pub fn save_api_endpoint(contents: &str) {
-    fs::write("schemas/api_schemas.json", contents).expect("Failed to write API ENDPOINTs");
+pub fn save_api_endpoint(contents: &str) -> Result<(), AgentError> {
+    fs::create_dir_all("schemas").map_err(AgentError::io("Failed to create schemas directory"))?;
+    fs::write("schemas/api_schemas.json", contents)
+        .map_err(AgentError::io("Failed to write API ENDPOINTs"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ai_functions::aifunc_managing::convert_user_input_to_goal;
+    use crate::apis::llm_provider::with_provider;
+    use crate::helpers::test_support::{replay_answers, replay_cassette};
+    use crate::models::agents::agent_traits::ProjectScope;
 
     #[test]
     fn test_extending_ai_function() {
@@ -96,14 +417,67 @@ mod tests {
 
     #[tokio::test]
     async fn test_ai_task_request() {
-        let res = ai_task_request(
-            "Build me a webserver for making stock price requests".to_string(),
-            "Managing Agent",
-            "Defining User requirements",
-            convert_user_input_to_goal,
+        let mut memory: Vec<Message> = vec![];
+        let res = with_provider(
+            replay_cassette("ai_task_request"),
+            ai_task_request(
+                &mut memory,
+                "Build me a webserver for making stock price requests".to_string(),
+                "Managing Agent",
+                "Defining User requirements",
+                convert_user_input_to_goal,
+            ),
         )
-        .await;
+        .await
+        .unwrap();
 
-        dbg!(res);
+        dbg!(&res);
+        assert!(res.starts_with("build a website"));
+        assert_eq!(memory.len(), 2);
+        assert_eq!(memory[1].content, res);
+    }
+
+    #[tokio::test]
+    async fn tests_decode_repairs_bad_answers() {
+        let mut memory: Vec<Message> = vec![];
+        let scope: ProjectScope = with_provider(
+            replay_answers(&[
+                "{\"is_crud_required\": \"yes\"}",
+                "Fixed:\n```json\n{\"is_crud_required\": true, \"is_user_login_and_logout\": false, \"is_external_urls_required\": false,}\n```",
+            ]),
+            ai_task_request_decoded(
+                &mut memory,
+                "Build a todo list api".to_string(),
+                "Solutions Architect",
+                "print_project_scope",
+                convert_user_input_to_goal,
+            ),
+        )
+        .await
+        .unwrap();
+        assert!(scope.is_crud_required);
+        // Only the accepted answer is remembered, as it was decoded
+        assert_eq!(memory.len(), 2);
+        assert!(memory[1].content.starts_with("{\"is_crud_required\": true"));
+
+        let err = with_provider(
+            replay_answers(&["not json", "still not json", "{\"is_crud_required\": 1}"]),
+            ai_task_request_decoded::<ProjectScope>(
+                &mut memory,
+                "Build a todo list api".to_string(),
+                "Solutions Architect",
+                "print_project_scope",
+                convert_user_input_to_goal,
+            ),
+        )
+        .await
+        .unwrap_err();
+        match err {
+            AgentError::Decode { message, .. } => {
+                assert!(message.contains("after 2 repair attempts"))
+            }
+            other => panic!("expected decode error, got {}", other),
+        }
+        assert_eq!(memory.len(), 2);
     }
 }
@@ -0,0 +1,99 @@
+// Lenient clean up of almost-JSON printed by a model, tried before asking the model again.
+// Handles code fences, prose around the value, smart quotes and trailing commas.
+pub fn repair_json(text: &str) -> String {
+    let text: String = text
+        .replace(['\u{201c}', '\u{201d}'], "\"")
+        .replace(['\u{2018}', '\u{2019}'], "'");
+    let text: &str = strip_code_fence(&text);
+    let text: &str = outer_value(text);
+    remove_trailing_commas(text)
+}
+
+// Contents of the first ``` fenced block, or the text itself without one
+fn strip_code_fence(text: &str) -> &str {
+    let Some(start) = text.find("```") else {
+        return text;
+    };
+    let after_fence: &str = &text[start + 3..];
+    // Skip the language tag on the fence line
+    let body: &str = match after_fence.find('\n') {
+        Some(newline) => &after_fence[newline + 1..],
+        None => after_fence,
+    };
+    match body.find("```") {
+        Some(end) => &body[..end],
+        None => body,
+    }
+}
+
+// From the first { or [ to the last matching closer, dropping commentary around the value
+fn outer_value(text: &str) -> &str {
+    let Some(start) = text.find(['{', '[']) else {
+        return text.trim();
+    };
+    let closer: char = match &text[start..start + 1] {
+        "{" => '}',
+        _ => ']',
+    };
+    match text.rfind(closer) {
+        Some(end) if end > start => &text[start..=end],
+        _ => &text[start..],
+    }
+}
+
+// Drops commas directly followed by } or ], leaving string contents alone
+fn remove_trailing_commas(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out: String = String::with_capacity(text.len());
+    let mut in_string: bool = false;
+    let mut escaped: bool = false;
+
+    for (index, c) in chars.iter().enumerate() {
+        if in_string {
+            match (escaped, c) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_string = false,
+                _ => {}
+            }
+            out.push(*c);
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            ',' => {
+                let next = chars[index + 1..].iter().find(|c| !c.is_whitespace());
+                if matches!(next, Some('}') | Some(']')) {
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(*c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_repairs_common_model_mistakes() {
+        let raw = "Sure! Here are the endpoints:\n```json\n[\n  {\"route\": \"/health\", \"method\": \"get\",},\n]\n```\nLet me know if you need more.";
+        let repaired = repair_json(raw);
+        let value: serde_json::Value = serde_json::from_str(&repaired).unwrap();
+        assert_eq!(value[0]["route"], "/health");
+
+        let raw = "{\u{201c}is_crud_required\u{201d}: true, \"note\": \"a, ]\",}";
+        let value: serde_json::Value = serde_json::from_str(&repair_json(raw)).unwrap();
+        assert_eq!(value["is_crud_required"], true);
+        assert_eq!(value["note"], "a, ]");
+    }
+
+    #[test]
+    fn tests_valid_json_is_left_alone() {
+        let raw = "[\"https://api.frankfurter.app/latest\"]";
+        assert_eq!(repair_json(raw), raw);
+    }
+}
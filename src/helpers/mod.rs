@@ -1,4 +1,12 @@
 // mod command_line;
+pub mod cargo_diagnostics;
+pub mod code_extract;
 pub mod command_line;
 pub mod general;
+pub mod json_repair;
+pub mod prompt_library;
+pub mod prompt_packing;
+pub mod redact;
+#[cfg(test)]
+pub mod test_support;
 // pub use command_line::get_user_response;
@@ -0,0 +1,387 @@
+use dotenv::dotenv;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::error::AgentError;
+use crate::helpers::general::extend_ai_function;
+use crate::models::general::llm::Message;
+
+// An input and the answer expected for it, shown to the model ahead of the real input
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct FewShot {
+    pub input: String,
+    pub output: String,
+}
+
+// Version of the compiled-in ai_functions, also selects them over library prompts
+pub const BUILTIN_VERSION: &str = "builtin";
+
+// A prompt loaded at runtime in place of the compiled-in ai_function of the same name.
+// {{input}} is the function input, any other {{name}} comes from `variables`.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct PromptTemplate {
+    // Defaults to the file stem
+    #[serde(default)]
+    pub name: String,
+    // Recorded with every exchange in the run journal, several versions of a name may
+    // be loaded side by side
+    #[serde(default = "default_version")]
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    #[serde(default)]
+    pub examples: Vec<FewShot>,
+}
+
+impl PromptTemplate {
+    // A .toml file holds every field, a .md file is the template with the rest as
+    // TOML front matter between +++ lines
+    pub fn from_file(path: &Path) -> Result<Self, AgentError> {
+        let contents: String = fs::read_to_string(path).map_err(AgentError::io(format!(
+            "Failed to read prompt {}",
+            path.display()
+        )))?;
+        let is_markdown: bool = path.extension().is_some_and(|ext| ext == "md");
+        let parsed = match is_markdown {
+            true => Self::from_markdown(&contents),
+            false => toml::from_str::<Self>(&contents).map_err(|e| e.to_string()),
+        };
+
+        let mut prompt: Self =
+            parsed.map_err(|e| AgentError::Config(format!("prompt {}: {}", path.display(), e)))?;
+        if prompt.name.is_empty() {
+            let stem = path.file_stem().unwrap_or_default();
+            prompt.name = stem.to_string_lossy().to_string();
+        }
+        Ok(prompt)
+    }
+
+    fn from_markdown(contents: &str) -> Result<Self, String> {
+        let front_matter = contents
+            .strip_prefix("+++")
+            .and_then(|rest| rest.split_once("\n+++"));
+        match front_matter {
+            Some((header, body)) => {
+                let mut prompt: Self = toml::from_str(header).map_err(|e| e.to_string())?;
+                prompt.template = body.trim_start_matches(['\r', '\n']).to_string();
+                Ok(prompt)
+            }
+            None => Ok(Self {
+                name: String::new(),
+                version: default_version(),
+                description: None,
+                template: contents.to_string(),
+                variables: BTreeMap::new(),
+                examples: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn render(&self, input: &str) -> Result<String, AgentError> {
+        let mut rendered: String = String::new();
+        let mut rest: &str = &self.template;
+        let mut has_input: bool = false;
+
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            rendered.push_str(&rest[..start]);
+            let variable: &str = rest[start + 2..start + len].trim();
+            match (variable, self.variables.get(variable)) {
+                ("input", _) => {
+                    rendered.push_str(input);
+                    has_input = true;
+                }
+                (_, Some(value)) => rendered.push_str(value),
+                (_, None) => {
+                    return Err(AgentError::Config(format!(
+                        "prompt {}: no value for {{{{{}}}}}",
+                        self.name, variable
+                    )))
+                }
+            }
+            rest = &rest[start + len + 2..];
+        }
+        rendered.push_str(rest);
+
+        for (n, example) in self.examples.iter().enumerate() {
+            rendered.push_str(&format!(
+                "\nEXAMPLE {}:\nINPUT: {}\nOUTPUT: {}\n",
+                n + 1,
+                example.input.trim(),
+                example.output.trim()
+            ));
+        }
+        if !has_input {
+            rendered.push_str(&format!("\nINPUT: {}\n", input));
+        }
+        Ok(rendered)
+    }
+}
+
+fn default_version() -> String {
+    "1".to_string()
+}
+
+// Orders "v2" before "v10"
+fn version_key(version: &str) -> (Vec<u64>, &str) {
+    let numbers: Vec<u64> = version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|digits| digits.parse().ok())
+        .collect();
+    (numbers, version)
+}
+
+// Prompts from LLM_PROMPTS_DIR (default "prompts") by name. Names missing from the
+// directory, or a missing directory, fall back to the compiled-in ai_functions.
+// The latest version of a name is used unless another one is selected, see
+// with_prompt_versions and LLM_PROMPT_VERSIONS.
+#[derive(Debug, Default)]
+pub struct PromptLibrary {
+    // Sorted oldest version first
+    prompts: BTreeMap<String, Vec<PromptTemplate>>,
+}
+
+impl PromptLibrary {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let dir: &Path = dir.as_ref();
+        if !dir.is_dir() {
+            return Ok(Self::default());
+        }
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(AgentError::io(format!(
+                "Failed to list prompts in {}",
+                dir.display()
+            )))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "toml" || ext == "md")
+            })
+            .collect();
+        paths.sort();
+
+        let mut prompts: BTreeMap<String, Vec<PromptTemplate>> = BTreeMap::new();
+        for path in paths {
+            let prompt: PromptTemplate = PromptTemplate::from_file(&path)?;
+            let versions: &mut Vec<PromptTemplate> =
+                prompts.entry(prompt.name.clone()).or_default();
+            if prompt.version == BUILTIN_VERSION
+                || versions.iter().any(|other| other.version == prompt.version)
+            {
+                return Err(AgentError::Config(format!(
+                    "prompt {} version {} is defined twice in {}",
+                    prompt.name,
+                    prompt.version,
+                    dir.display()
+                )));
+            }
+            versions.push(prompt);
+            versions.sort_by(|a, b| version_key(&a.version).cmp(&version_key(&b.version)));
+        }
+        Ok(Self { prompts })
+    }
+
+    pub fn from_env() -> Result<Self, AgentError> {
+        dotenv().ok();
+        let dir: String = env::var("LLM_PROMPTS_DIR").unwrap_or_else(|_| "prompts".to_string());
+        Self::load(dir)
+    }
+
+    // The selected version of a prompt, None when the compiled-in one applies
+    pub fn get(&self, name: &str) -> Result<Option<&PromptTemplate>, AgentError> {
+        let versions: &[PromptTemplate] = self.prompts.get(name).map_or(&[], Vec::as_slice);
+        match selected_version(name)? {
+            Some(version) if version == BUILTIN_VERSION => Ok(None),
+            Some(version) => match versions.iter().find(|prompt| prompt.version == version) {
+                Some(prompt) => Ok(Some(prompt)),
+                None => Err(AgentError::Config(format!(
+                    "prompt {} has no version {}",
+                    name, version
+                ))),
+            },
+            None => Ok(versions.last()),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prompts.keys().map(String::as_str)
+    }
+
+    pub fn has_version(&self, name: &str, version: &str) -> bool {
+        version == BUILTIN_VERSION
+            || self
+                .prompts
+                .get(name)
+                .is_some_and(|versions| versions.iter().any(|prompt| prompt.version == version))
+    }
+
+    // Version the next request for `name` will use
+    pub fn version(&self, name: &str) -> Result<String, AgentError> {
+        Ok(self
+            .get(name)?
+            .map_or(BUILTIN_VERSION.to_string(), |prompt| prompt.version.clone()))
+    }
+
+    // The named prompt from the library, otherwise the compiled-in ai_function
+    pub fn message(
+        &self,
+        name: &str,
+        fallback: fn(&str) -> &'static str,
+        input: &str,
+    ) -> Result<Message, AgentError> {
+        match self.get(name)? {
+            Some(prompt) => Ok(Message {
+                role: "system".to_string(),
+                content: prompt.render(input)?,
+            }),
+            None => Ok(extend_ai_function(fallback, input)),
+        }
+    }
+}
+
+tokio::task_local! {
+    static PROMPT_VERSIONS: BTreeMap<String, String>;
+}
+
+// Every request inside `fut` uses the given prompt version per ai_function name
+pub async fn with_prompt_versions<F: Future>(
+    versions: BTreeMap<String, String>,
+    fut: F,
+) -> F::Output {
+    PROMPT_VERSIONS.scope(versions, fut).await
+}
+
+// From with_prompt_versions, otherwise LLM_PROMPT_VERSIONS such as
+// "print_rest_api_endpoints=v2,print_fixed_code=builtin"
+fn selected_version(name: &str) -> Result<Option<String>, AgentError> {
+    if let Ok(version) = PROMPT_VERSIONS.try_with(|versions| versions.get(name).cloned()) {
+        return Ok(version);
+    }
+    let Ok(selection) = env::var("LLM_PROMPT_VERSIONS") else {
+        return Ok(None);
+    };
+    for entry in selection
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+    {
+        let Some((prompt, version)) = entry.split_once('=') else {
+            return Err(AgentError::Config(format!(
+                "LLM_PROMPT_VERSIONS: expected name=version, got {}",
+                entry
+            )));
+        };
+        if prompt.trim() == name {
+            return Ok(Some(version.trim().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+// Loaded once per run, so edits to the prompt files apply from the next run on
+static PROMPT_LIBRARY: OnceLock<PromptLibrary> = OnceLock::new();
+
+pub fn prompt_library() -> Result<&'static PromptLibrary, AgentError> {
+    if let Some(library) = PROMPT_LIBRARY.get() {
+        return Ok(library);
+    }
+    let library = PromptLibrary::from_env()?;
+    Ok(PROMPT_LIBRARY.get_or_init(|| library))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_functions::aifunc_backend::print_fixed_code;
+
+    #[test]
+    fn tests_library_prompts_replace_compiled_ones() {
+        let dir = env::temp_dir().join(format!("auto_gippity_prompts_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("print_fixed_code.toml"),
+            r#"
+description = "Fixes Rust code"
+template = "Fix this {{language}} code: {{ input }}"
+variables = { language = "Rust" }
+
+[[examples]]
+input = "fn main() { let x: u8 = 256; }"
+output = "fn main() { let x: u16 = 256; }"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("goal_v10.md"),
+            "+++\nname = \"print_goal\"\nversion = \"v10\"\n+++\nState the goal.\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("goal_v2.md"),
+            "+++\nname = \"print_goal\"\nversion = \"v2\"\n+++\nSummarise the request as a goal.\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a prompt").unwrap();
+
+        let library = PromptLibrary::load(&dir).unwrap();
+        assert_eq!(
+            library.names().collect::<Vec<_>>(),
+            vec!["print_fixed_code", "print_goal"]
+        );
+
+        let fixed = library
+            .message("print_fixed_code", print_fixed_code, "fn main() {")
+            .unwrap();
+        assert!(fixed
+            .content
+            .starts_with("Fix this Rust code: fn main() {\nEXAMPLE 1:\n"));
+        assert!(!fixed.content.contains("\nINPUT: fn main() {\n"));
+
+        // The latest version unless another one is selected
+        assert_eq!(library.version("print_goal").unwrap(), "v10");
+        assert_eq!(library.version("print_fixed_code").unwrap(), "1");
+        assert_eq!(library.version("print_improved_code").unwrap(), "builtin");
+        let selected: BTreeMap<String, String> =
+            BTreeMap::from([("print_goal".to_string(), "v2".to_string())]);
+        let goal = PROMPT_VERSIONS.sync_scope(selected, || {
+            assert_eq!(library.version("print_goal").unwrap(), "v2");
+            library
+                .message("print_goal", print_fixed_code, "a todo app")
+                .unwrap()
+        });
+        // Without {{input}} the input goes last
+        assert_eq!(
+            goal.content,
+            "Summarise the request as a goal.\n\nINPUT: a todo app\n"
+        );
+        let unknown: BTreeMap<String, String> =
+            BTreeMap::from([("print_goal".to_string(), "v3".to_string())]);
+        assert!(PROMPT_VERSIONS.sync_scope(unknown, || library.version("print_goal").is_err()));
+
+        // Unknown names use the compiled-in function
+        let compiled = library
+            .message("print_improved_code", print_fixed_code, "fn main() {")
+            .unwrap();
+        assert_eq!(
+            compiled,
+            extend_ai_function(print_fixed_code, "fn main() {")
+        );
+
+        fs::write(dir.join("broken.md"), "Uses {{missing}}").unwrap();
+        let library = PromptLibrary::load(&dir).unwrap();
+        assert!(library.message("broken", print_fixed_code, "").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+use dotenv::dotenv;
+use std::{collections::BTreeSet, env};
+use tiktoken_rs::{
+    cl100k_base_singleton, model::get_context_size, o200k_base_singleton, tokenizer, CoreBPE,
+};
+
+use crate::apis::llm_provider::provider_for;
+use crate::error::AgentError;
+use crate::helpers::command_line::PrintCommand;
+use crate::helpers::general::function_message;
+use crate::models::agent_basic::memory::MemoryWindow;
+use crate::models::general::llm::Message;
+
+// Room left for the answer, code answers are long
+const OUTPUT_RESERVE_TOKENS: usize = 4_096;
+
+// Context size assumed for models we know nothing about, such as most local ones
+const DEFAULT_CONTEXT_TOKENS: usize = 8_192;
+
+// OpenAI models are counted with their own tokenizer. Others (Claude, Llama ...) do not publish
+// one we can use offline, cl100k comes within a few percent for English and code.
+fn tokenizer_for(model: &str) -> &'static CoreBPE {
+    match tokenizer::get_tokenizer(model) {
+        Some(tokenizer::Tokenizer::O200kBase) => o200k_base_singleton(),
+        _ => cl100k_base_singleton(),
+    }
+}
+
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    tokenizer_for(model).encode_ordinary(text).len()
+}
+
+// LLM_CONTEXT_TOKENS overrides the table, e.g. for an Ollama model run with a larger num_ctx
+pub fn context_window(model: &str) -> usize {
+    dotenv().ok();
+    if let Some(tokens) = env::var("LLM_CONTEXT_TOKENS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+    {
+        return tokens;
+    }
+    match model {
+        model if model.starts_with("claude") => 200_000,
+        model if tokenizer::get_tokenizer(model).is_some() => get_context_size(model),
+        _ => DEFAULT_CONTEXT_TOKENS,
+    }
+}
+
+// Tokens the request and the turns sent with it can take, the rest is left for the answer
+pub fn prompt_room(model: &str) -> usize {
+    context_window(model).saturating_sub(OUTPUT_RESERVE_TOKENS)
+}
+
+// One labelled part of a prompt. Lower priority numbers get their tokens first;
+// a section that does not fit is cut at a line boundary, one with no room left is dropped.
+// Priority 0 sections are never dropped, packing fails instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptSection {
+    pub label: String,
+    pub priority: u8,
+    pub content: String,
+}
+
+impl PromptSection {
+    pub fn new(label: &str, priority: u8, content: impl Into<String>) -> Self {
+        Self {
+            label: label.to_string(),
+            priority,
+            content: content.into(),
+        }
+    }
+
+    fn text(&self) -> String {
+        format!("{}: {} \n", self.label, self.content)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedPrompt {
+    pub text: String,
+    // What was cut or left out, to be shown to the user
+    pub warnings: Vec<String>,
+}
+
+// Fits the sections into budget tokens, keeping them in the order given
+pub fn pack_sections(
+    model: &str,
+    budget: usize,
+    sections: Vec<PromptSection>,
+) -> Result<PackedPrompt, AgentError> {
+    let mut order: Vec<usize> = (0..sections.len()).collect();
+    order.sort_by_key(|&index| sections[index].priority);
+
+    let mut kept: Vec<Option<String>> = vec![None; sections.len()];
+    let mut warnings: Vec<String> = vec![];
+    let mut remaining: usize = budget;
+
+    for index in order {
+        let section: &PromptSection = &sections[index];
+        let text: String = section.text();
+        let tokens: usize = count_tokens(model, &text);
+        if tokens <= remaining {
+            remaining -= tokens;
+            kept[index] = Some(text);
+            continue;
+        }
+
+        let cut: String = truncate_lines(model, &text, remaining);
+        match cut.is_empty() {
+            true if section.priority == 0 => {
+                return Err(AgentError::Config(format!(
+                    "{} ({} tokens) does not fit the {} token prompt budget of {}",
+                    section.label, tokens, budget, model
+                )))
+            }
+            true => warnings.push(format!(
+                "{} left out ({} tokens, no room in the {} token prompt budget)",
+                section.label, tokens, budget
+            )),
+            false => {
+                let cut_tokens: usize = count_tokens(model, &cut);
+                remaining -= cut_tokens;
+                warnings.push(format!(
+                    "{} cut from {} to {} tokens",
+                    section.label, tokens, cut_tokens
+                ));
+                kept[index] = Some(cut);
+            }
+        }
+    }
+
+    Ok(PackedPrompt {
+        text: kept.into_iter().flatten().collect(),
+        warnings,
+    })
+}
+
+// Whole lines from the start while they fit, marked as cut
+fn truncate_lines(model: &str, text: &str, budget: usize) -> String {
+    const MARKER: &str = "[... truncated to fit the context window]\n";
+    let Some(budget) = budget.checked_sub(count_tokens(model, MARKER)) else {
+        return String::new();
+    };
+
+    let mut cut: String = String::new();
+    let mut used: usize = 0;
+    for line in text.split_inclusive('\n') {
+        let tokens: usize = count_tokens(model, line);
+        if used + tokens > budget {
+            break;
+        }
+        used += tokens;
+        cut.push_str(line);
+    }
+    match cut.is_empty() {
+        true => cut,
+        false => cut + MARKER,
+    }
+}
+
+// Error blocks of cargo build stderr, the first max_errors of them.
+// Warnings and the closing "could not compile" lines are left out.
+// Also returns how many errors were left out.
+pub fn first_compiler_errors(stderr: &str, max_errors: usize) -> (String, usize) {
+    let mut blocks: Vec<String> = vec![];
+    let mut current: Option<String> = None;
+
+    for line in stderr.lines() {
+        let starts_block: bool = line.starts_with("error") || line.starts_with("warning");
+        if starts_block {
+            blocks.extend(current.take());
+            let is_error: bool = line.starts_with("error")
+                && !line.starts_with("error: could not compile")
+                && !line.starts_with("error: aborting");
+            if is_error {
+                current = Some(String::new());
+            }
+        } else if line.trim_start().starts_with("Compiling ") {
+            blocks.extend(current.take());
+            continue;
+        }
+        if let Some(block) = current.as_mut() {
+            block.push_str(line);
+            block.push('\n');
+        }
+    }
+    blocks.extend(current);
+
+    if blocks.is_empty() {
+        return (stderr.to_string(), 0);
+    }
+    let omitted: usize = blocks.len().saturating_sub(max_errors);
+    let mut errors: String = blocks
+        .into_iter()
+        .take(max_errors)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if omitted > 0 {
+        errors.push_str(&format!("\n... and {} more errors\n", omitted));
+    }
+    (errors, omitted)
+}
+
+// Files the errors point at (` --> src/routes.rs:4:5`), relative to the crate
+pub fn compiler_error_files(errors: &str) -> BTreeSet<String> {
+    errors
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("--> "))
+        .filter_map(|location| location.split(':').next())
+        .map(|path| path.trim().trim_start_matches("./").to_string())
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+// Packs the sections into what the ai_function's model has left once the function
+// template, the agent's remembered turns and room for the answer are accounted for.
+// The turns give way to the priority 0 sections. Anything cut is reported as an issue.
+pub fn fit_prompt(
+    memory: &[Message],
+    agent_position: &str,
+    agent_operation: &str,
+    function_pass: for<'a> fn(&'a str) -> &'static str,
+    sections: Vec<PromptSection>,
+) -> Result<String, AgentError> {
+    let model: String = provider_for(agent_operation)?.model().to_string();
+
+    let template: Message = function_message(agent_operation, function_pass, "")?;
+    let room: usize = prompt_room(&model).saturating_sub(count_tokens(&model, &template.content));
+
+    let packed: PackedPrompt = fit_sections(&model, room, memory, sections)?;
+    for warning in &packed.warnings {
+        let msg: String = format!("Prompt trimmed for {}: {}", model, warning);
+        PrintCommand::Issue.print_agent_message(agent_position, msg.as_str());
+    }
+    Ok(packed.text)
+}
+
+// The agent's remembered turns only get what the priority 0 sections leave of room,
+// the other sections what the turns leave
+fn fit_sections(
+    model: &str,
+    room: usize,
+    memory: &[Message],
+    sections: Vec<PromptSection>,
+) -> Result<PackedPrompt, AgentError> {
+    let essential_tokens: usize = sections
+        .iter()
+        .filter(|section| section.priority == 0)
+        .map(|section| count_tokens(model, &section.text()))
+        .sum();
+    let memory_tokens: usize = MemoryWindow::for_model(model)
+        .within(room.saturating_sub(essential_tokens))
+        .context(model, memory)
+        .iter()
+        .map(|msg| count_tokens(model, &msg.content))
+        .sum();
+    pack_sections(model, room.saturating_sub(memory_tokens), sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::agent_basic::memory::remember;
+
+    #[test]
+    fn tests_sections_are_packed_by_priority() {
+        let code: String = (0..200)
+            .map(|i| format!("let value_{} = {};\n", i, i))
+            .collect();
+        let sections = vec![
+            PromptSection::new("PROJECT_DESCRIPTION", 0, "a health check server"),
+            PromptSection::new("CODE TEMPLATE", 1, code.clone()),
+            PromptSection::new("EXTERNAL_URLS", 2, "https://worldtimeapi.org/api/timezone"),
+        ];
+
+        let packed = pack_sections("gpt-4o", 10_000, sections.clone()).unwrap();
+        assert!(packed.warnings.is_empty());
+        assert!(packed
+            .text
+            .starts_with("PROJECT_DESCRIPTION: a health check server \n"));
+        assert!(packed.text.contains(&code));
+
+        let packed = pack_sections("gpt-4o", 300, sections).unwrap();
+        assert!(count_tokens("gpt-4o", &packed.text) <= 300);
+        assert!(packed.text.starts_with("PROJECT_DESCRIPTION"));
+        assert!(packed.text.contains("let value_0 = 0;"));
+        assert!(packed
+            .text
+            .contains("[... truncated to fit the context window]"));
+        assert!(!packed.text.contains("EXTERNAL_URLS"));
+        assert_eq!(packed.warnings.len(), 2);
+        assert!(packed.warnings[1].starts_with("EXTERNAL_URLS left out"));
+    }
+
+    #[test]
+    fn tests_memory_gives_way_to_essential_sections() {
+        let mut memory: Vec<Message> = vec![];
+        let answer: String = "fn main() { println!(\"hello\"); }\n".repeat(400);
+        for turn in 0..4 {
+            remember(
+                &mut memory,
+                &format!("write the server, take {}", turn),
+                &answer,
+            );
+        }
+        let memory_tokens: usize = memory
+            .iter()
+            .map(|msg| count_tokens("gpt-4o", &msg.content))
+            .sum();
+        assert!(memory_tokens > 2_000);
+
+        let sections = vec![
+            PromptSection::new("PROJECT_DESCRIPTION", 0, "a health check server"),
+            PromptSection::new("CODE TEMPLATE", 1, answer.clone()),
+        ];
+        let packed = fit_sections("gpt-4o", 2_000, &memory, sections.clone()).unwrap();
+        assert!(packed
+            .text
+            .starts_with("PROJECT_DESCRIPTION: a health check server \n"));
+
+        // What the turns sent along leave is enough for the sections
+        let window =
+            MemoryWindow::for_model("gpt-4o").within(2_000 - count_tokens("gpt-4o", &packed.text));
+        let sent: usize = window
+            .context("gpt-4o", &memory)
+            .iter()
+            .map(|msg| count_tokens("gpt-4o", &msg.content))
+            .sum();
+        assert!(sent + count_tokens("gpt-4o", &packed.text) <= 2_000);
+
+        // Cut rather than dropped, and an error once not even that fits
+        let long: String = "a health check server\n".repeat(50);
+        let sections = vec![PromptSection::new("PROJECT_DESCRIPTION", 0, long)];
+        let packed = fit_sections("gpt-4o", 100, &memory, sections.clone()).unwrap();
+        assert!(packed
+            .text
+            .starts_with("PROJECT_DESCRIPTION: a health check server\n"));
+        assert_eq!(packed.warnings.len(), 1);
+        match fit_sections("gpt-4o", 5, &memory, sections).unwrap_err() {
+            AgentError::Config(message) => assert!(message.starts_with("PROJECT_DESCRIPTION")),
+            other => panic!("expected config error, got {}", other),
+        }
+    }
+
+    #[test]
+    fn tests_keeps_first_compiler_errors() {
+        let stderr = "   Compiling backend v0.1.0\n\
+warning: unused import: `std::fs`\n --> src/main.rs:1:5\n\n\
+error[E0425]: cannot find value `x` in this scope\n --> src/main.rs:4:5\n\n\
+error[E0308]: mismatched types\n --> src/main.rs:9:5\n\n\
+error[E0599]: no method named `foo`\n --> src/main.rs:12:5\n\n\
+error: could not compile `backend` (bin \"backend\") due to 3 previous errors\n";
+
+        let (errors, omitted) = first_compiler_errors(stderr, 2);
+        assert_eq!(omitted, 1);
+        assert!(errors.starts_with("error[E0425]"));
+        assert!(errors.contains("error[E0308]"));
+        assert!(!errors.contains("E0599"));
+        assert!(!errors.contains("unused import"));
+        assert!(errors.ends_with("... and 1 more errors\n"));
+        assert_eq!(
+            compiler_error_files(&errors),
+            BTreeSet::from(["src/main.rs".to_string()])
+        );
+        assert!(compiler_error_files("error: linker `cc` not found").is_empty());
+    }
+}
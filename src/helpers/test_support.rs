@@ -0,0 +1,84 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::apis::llm_provider::{LlmProvider, ProviderConfig, ProviderKind};
+use crate::apis::mock_llm_server::MockLlmServer;
+use crate::apis::provider_openai::OpenAiProvider;
+use crate::apis::provider_replay::{Cassette, Interaction, ReplayProvider};
+
+pub fn fixture_path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(relative)
+}
+
+// Replay provider over fixtures/cassettes/<name>.json
+pub fn replay_cassette(name: &str) -> Arc<dyn LlmProvider> {
+    let path = fixture_path(&format!("cassettes/{}.json", name));
+    Arc::new(ReplayProvider::from_file(path, false).expect("Failed to load cassette"))
+}
+
+// The real OpenAI provider against the local chat completions stand-in serving the cassette.
+// The server stops when it is dropped.
+pub async fn mock_openai(name: &str) -> (MockLlmServer, Arc<OpenAiProvider>) {
+    let path = fixture_path(&format!("cassettes/{}.json", name));
+    let replay = ReplayProvider::from_file(path, false).expect("Failed to load cassette");
+    let server = MockLlmServer::start(Arc::new(replay))
+        .await
+        .expect("Failed to start mock llm server");
+    let mut config = ProviderConfig::new(ProviderKind::OpenAi);
+    config.base_url = server.base_url.clone();
+    config.api_key = Some("test-key".to_string());
+    let provider = Arc::new(OpenAiProvider::new(&config).expect("Failed to build provider"));
+    (server, provider)
+}
+
+// Replay provider answering with these responses in order, whatever is asked
+pub fn replay_answers(responses: &[&str]) -> Arc<dyn LlmProvider> {
+    let interactions = responses
+        .iter()
+        .map(|response| Interaction {
+            request: vec![],
+            response: response.to_string(),
+            model: None,
+            usage: None,
+        })
+        .collect();
+    Arc::new(ReplayProvider::new(Cassette { interactions }, false))
+}
+
+static BACKEND_WORKSPACE: Mutex<()> = Mutex::const_new(());
+
+// Fresh copy of the fixture backend crate with TEMPLATE_PATH and EXEC_PATH pointing at it,
+// run workspaces are scaffolded under its runs directory.
+// Tests building and running the backend share the env vars and port 8080, so the
+// returned guard must be held for the whole test.
+pub async fn backend_workspace() -> MutexGuard<'static, ()> {
+    let guard = BACKEND_WORKSPACE.lock().await;
+
+    let workspace: PathBuf =
+        env::temp_dir().join(format!("auto_gippity_backend_{}", std::process::id()));
+    let template: PathBuf = fixture_path("backend_template");
+    if workspace.exists() {
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+    fs::create_dir_all(workspace.join("src")).unwrap();
+    fs::copy(template.join("Cargo.toml"), workspace.join("Cargo.toml")).unwrap();
+    for file in ["main.rs", "_code_template.rs"] {
+        fs::copy(
+            template.join("src").join(file),
+            workspace.join("src").join(file),
+        )
+        .unwrap();
+    }
+
+    env::set_var("TEMPLATE_PATH", workspace.join("src"));
+    env::set_var("EXEC_PATH", &workspace);
+    env::set_var("LLM_RUNS_DIR", workspace.join("runs"));
+    env::set_var("AUTO_CONFIRM_SAFE_CODE", "1");
+    guard
+}
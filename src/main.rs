@@ -1,5 +1,9 @@
+use apis::llm_provider::{current_provider, LlmProvider};
 use helpers::command_line::get_user_response;
+use helpers::prompt_library::prompt_library;
 use models::agent_managers::managing_agent::ManagingAgent;
+use models::general::prompt_eval::run_eval;
+use models::general::starter_templates::{StarterTemplate, STARTER_TEMPLATES};
 
 #[macro_export]
 macro_rules! get_function_string {
@@ -11,17 +15,149 @@ macro_rules! get_function_string {
 #[macro_use]
 mod ai_functions;
 mod apis;
+mod error;
 mod helpers;
 mod models;
 
 #[tokio::main]
 async fn main() {
+    // Flags can come before or after a subcommand, what is left are its words
+    let (flags, args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    if flags.iter().any(|arg| arg == "--no-cache") {
+        std::env::set_var("LLM_CACHE", "0");
+    }
+
+    // auto-gippity templates list | templates new <template> <dir>
+    if args.first().is_some_and(|arg| arg == "templates") {
+        if let Err(e) = templates_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let provider = match current_provider() {
+        Ok(provider) => provider,
+        Err(e) => {
+            eprintln!("Failed to configure llm provider: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("Using {} model {}", provider.name(), provider.model());
+    probe_models(provider.as_ref()).await;
+
+    // A broken prompt file stops the run here rather than at its ai_function
+    match prompt_library() {
+        Ok(library) => {
+            let names: Vec<String> = library
+                .names()
+                .map(|name| {
+                    let version: String = library.version(name).unwrap_or_default();
+                    format!("{}@{}", name, version)
+                })
+                .collect();
+            if !names.is_empty() {
+                println!("Prompts loaded from library: {}", names.join(", "));
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to load prompt library: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // auto-gippity eval <suite.toml>: compare two prompt versions instead of building a project
+    if let Some(eval_at) = args.iter().position(|arg| arg == "eval") {
+        let Some(suite) = args.get(eval_at + 1) else {
+            eprintln!("Usage: auto-gippity eval <suite.toml>");
+            std::process::exit(2);
+        };
+        if let Err(e) = run_eval(std::path::Path::new(suite)).await {
+            eprintln!("Eval stopped: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let user_req: String = get_user_response("What webserver are we building today ? \n");
 
-    let mut manage_agent: ManagingAgent = ManagingAgent::new(user_req)
-        .await
-        .expect("Error creating managing agent");
-    manage_agent.execute_project().await;
+    let mut manage_agent: ManagingAgent = match ManagingAgent::new(user_req).await {
+        Ok(agent) => agent,
+        Err(e) => {
+            eprintln!("Error creating managing agent: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = manage_agent.execute_project().await {
+        eprintln!("Project stopped: {}", e);
+        if let Some(details) = e.details() {
+            eprintln!("{}", details);
+        }
+    }
 
     dbg!(manage_agent);
 }
+
+// Starter backend crates compiled into the binary, for EXEC_PATH and TEMPLATE_PATH
+// (<dir> and <dir>/src) or as a base to edit
+fn templates_command(args: &[String]) -> Result<(), error::AgentError> {
+    match args.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        ["list"] => {
+            for template in STARTER_TEMPLATES {
+                println!("{:<12} {}", template.name, template.description);
+            }
+            Ok(())
+        }
+        ["new", name, dir] => {
+            StarterTemplate::find(name)?.generate(std::path::Path::new(dir), true)?;
+            println!("Created {} backend in {}", name, dir);
+            println!(
+                "Set EXEC_PATH={} and TEMPLATE_PATH={}/src to use it",
+                dir, dir
+            );
+            Ok(())
+        }
+        _ => Err(error::AgentError::Config(
+            "usage: auto-gippity templates list | templates new <template> <dir>".to_string(),
+        )),
+    }
+}
+
+// Lists what the server offers, so a wrong model name or an unreachable local server
+// shows up before the first prompt rather than in the middle of a run
+async fn probe_models(provider: &dyn LlmProvider) {
+    const SHOWN: usize = 10;
+    let models: Vec<String> = match provider.list_models().await {
+        Ok(Some(models)) => models,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Warning: could not list models: {}", e);
+            return;
+        }
+    };
+
+    let mut listed: String = models
+        .iter()
+        .take(SHOWN)
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(", ");
+    if models.len() > SHOWN {
+        listed.push_str(&format!(" and {} more", models.len() - SHOWN));
+    }
+    println!("Server offers {} models: {}", models.len(), listed);
+
+    // Ollama lists untagged models as name:latest
+    let served: bool = models.iter().any(|name| {
+        name == provider.model() || name.strip_suffix(":latest") == Some(provider.model())
+    });
+    if !served {
+        eprintln!(
+            "Warning: model {} is not in the server's model list",
+            provider.model()
+        );
+    }
+}
@@ -8,6 +8,7 @@ pub enum AgentState {
     Finished,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct BasicAgent {
     pub objective: String,
@@ -1,5 +1,6 @@
 use crate::models::{agent_basic::basic_agent::AgentState, general::llm::Message};
 
+#[allow(dead_code)]
 pub trait BasicTrait {
     fn new(objective: String, position: String) -> Self;
     fn update_state(&mut self, new_state: AgentState);
@@ -0,0 +1,167 @@
+use dotenv::dotenv;
+use std::env;
+
+use crate::helpers::prompt_packing::{context_window, count_tokens};
+use crate::models::general::llm::Message;
+
+// Share of an answer kept when its turn is summarised instead of sent
+const SUMMARY_EXCERPT_CHARS: usize = 160;
+
+const DEFAULT_MAX_TURNS: usize = 4;
+
+// Earlier turns get at most this share of the model's context window by default
+const MEMORY_SHARE_DIVISOR: usize = 4;
+
+// How much of an agent's memory is sent along with a new request.
+// The most recent turns are sent whole while they fit, older ones as a one line summary each.
+// LLM_MEMORY_TURNS=0 sends no history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryWindow {
+    pub max_turns: usize,
+    // Tokens across the turns sent whole, counted with the model's tokenizer
+    pub max_tokens: usize,
+}
+
+impl MemoryWindow {
+    // LLM_MEMORY_TURNS and LLM_MEMORY_TOKENS, the token default is a quarter of the context window
+    pub fn for_model(model: &str) -> Self {
+        dotenv().ok();
+        let var = |name: &str| -> Option<usize> { env::var(name).ok()?.trim().parse().ok() };
+
+        Self {
+            max_turns: var("LLM_MEMORY_TURNS").unwrap_or(DEFAULT_MAX_TURNS),
+            max_tokens: var("LLM_MEMORY_TOKENS")
+                .unwrap_or_else(|| context_window(model) / MEMORY_SHARE_DIVISOR),
+        }
+    }
+
+    // The same window holding at most tokens
+    pub fn within(self, tokens: usize) -> Self {
+        Self {
+            max_tokens: self.max_tokens.min(tokens),
+            ..self
+        }
+    }
+
+    // Messages to put before the next request, max_tokens of them at most
+    pub fn context(&self, model: &str, memory: &[Message]) -> Vec<Message> {
+        if self.max_turns == 0 {
+            return vec![];
+        }
+        let turns: Vec<&[Message]> = memory.chunks(2).collect();
+
+        let mut kept: usize = 0;
+        let mut tokens: usize = 0;
+        for turn in turns.iter().rev() {
+            let turn_tokens: usize = turn
+                .iter()
+                .map(|msg| count_tokens(model, &msg.content))
+                .sum();
+            if kept == self.max_turns || tokens + turn_tokens > self.max_tokens {
+                break;
+            }
+            kept += 1;
+            tokens += turn_tokens;
+        }
+
+        let (older, recent) = turns.split_at(turns.len() - kept);
+        let mut context: Vec<Message> = vec![];
+        if !older.is_empty() {
+            // Left out too when even the summary has no room
+            let summary: Message = summarise(older);
+            if tokens + count_tokens(model, &summary.content) <= self.max_tokens {
+                context.push(summary);
+            }
+        }
+        context.extend(recent.iter().flat_map(|turn| turn.iter().cloned()));
+        context
+    }
+}
+
+// Records a request and its answer as one turn
+pub fn remember(memory: &mut Vec<Message>, request: &str, response: &str) {
+    memory.push(Message {
+        role: "user".to_string(),
+        content: request.to_string(),
+    });
+    memory.push(Message {
+        role: "assistant".to_string(),
+        content: response.to_string(),
+    });
+}
+
+fn summarise(turns: &[&[Message]]) -> Message {
+    let lines: Vec<String> = turns
+        .iter()
+        .enumerate()
+        .map(|(index, turn)| {
+            let answer: &str = turn
+                .iter()
+                .find(|msg| msg.role == "assistant")
+                .map(|msg| msg.content.as_str())
+                .unwrap_or("");
+            let excerpt: String = answer
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+                .chars()
+                .take(SUMMARY_EXCERPT_CHARS)
+                .collect();
+            format!(
+                "Turn {}: answered {} characters, starting: {}",
+                index + 1,
+                answer.len(),
+                excerpt
+            )
+        })
+        .collect();
+
+    Message {
+        role: "system".to_string(),
+        content: format!(
+            "EARLIER TURNS (summarised, the most recent turns follow in full):\n{}",
+            lines.join("\n")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_window_keeps_recent_turns_and_summarises_older() {
+        let mut memory: Vec<Message> = vec![];
+        remember(&mut memory, "write the server", "fn main() { broken }");
+        remember(&mut memory, "fix E0425", "fn main() { still broken }");
+        remember(&mut memory, "fix E0308", "fn main() {}");
+
+        let window = MemoryWindow {
+            max_turns: 2,
+            max_tokens: 1_000,
+        };
+        let context = window.context("gpt-4o", &memory);
+        assert_eq!(context.len(), 5);
+        assert_eq!(context[0].role, "system");
+        assert!(context[0]
+            .content
+            .contains("Turn 1: answered 20 characters, starting: fn main() { broken }"));
+        assert_eq!(context[1].content, "fix E0425");
+        assert_eq!(context[4].content, "fn main() {}");
+
+        // Only the last turn fits, without the summary of the others
+        let window = MemoryWindow {
+            max_turns: 4,
+            max_tokens: 12,
+        };
+        let context = window.context("gpt-4o", &memory);
+        assert_eq!(context.len(), 2);
+        assert_eq!(context[0].content, "fix E0308");
+
+        let window = MemoryWindow {
+            max_turns: 0,
+            max_tokens: 1_000,
+        };
+        assert!(window.context("gpt-4o", &memory).is_empty());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod basic_agent;
 pub mod basic_traits;
+pub mod memory;
@@ -1,12 +1,20 @@
+use std::{env, sync::Arc};
+
 use crate::{
     ai_functions::aifunc_managing::convert_user_input_to_goal,
-    helpers::general::ai_task_request,
+    error::AgentError,
+    helpers::{command_line::PrintCommand, general::ai_task_request, redact::redactor},
     models::{
         agent_basic::basic_agent::{AgentState, BasicAgent},
         agents::{
             agent_architect::AgentSolutionsArchitect,
             agent_backend::AgentBackendDeveloper,
-            agent_traits::{FactSheet, SpecialFunctions},
+            agent_traits::{BackendFramework, FactSheet, SpecialFunctions},
+        },
+        general::{
+            budget::Budget,
+            journal::{with_journal, RunJournal},
+            usage::{with_usage_tracker, PriceTable, UsageTracker},
         },
     },
 };
@@ -16,12 +24,14 @@ pub struct ManagingAgent {
     attributes: BasicAgent,
     factsheet: FactSheet,
     agents: Vec<Box<dyn SpecialFunctions>>,
+    usage: Arc<UsageTracker>,
+    journal: Option<Arc<RunJournal>>,
 }
 
 impl ManagingAgent {
-    pub async fn new(usr_req: String) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(usr_req: String) -> Result<Self, AgentError> {
         let position: String = "Project Manager".to_string();
-        let attributes: BasicAgent = BasicAgent {
+        let mut attributes: BasicAgent = BasicAgent {
             memory: vec![],
             objective: "Manage agents who are building an execellent website for the user"
                 .to_string(),
@@ -29,27 +39,46 @@ impl ManagingAgent {
             state: AgentState::Discovery,
         };
 
-        let project_description: String = ai_task_request(
-            usr_req,
-            &position,
-            get_function_string!(convert_user_input_to_goal),
-            convert_user_input_to_goal,
+        let usage: Arc<UsageTracker> = Arc::new(UsageTracker::new(
+            PriceTable::from_env()?,
+            Budget::from_env()?,
+        ));
+
+        let journal: Option<Arc<RunJournal>> = RunJournal::from_env()?.map(Arc::new);
+        let framework: BackendFramework = BackendFramework::from_env()?;
+
+        let project_description: String = with_usage_tracker(
+            usage.clone(),
+            with_journal(
+                journal.clone(),
+                ai_task_request(
+                    &mut attributes.memory,
+                    usr_req,
+                    &position,
+                    get_function_string!(convert_user_input_to_goal),
+                    convert_user_input_to_goal,
+                ),
+            ),
         )
-        .await;
+        .await?;
 
         let agents: Vec<Box<dyn SpecialFunctions>> = vec![];
         let factsheet: FactSheet = FactSheet {
             project_description,
+            framework,
             project_scope: None,
             external_url: None,
             backend_code: None,
             api_endpoint_schema: None,
+            workspace: None,
         };
 
         Ok(Self {
             agents,
             attributes,
             factsheet,
+            usage,
+            journal,
         })
     }
 
@@ -58,19 +87,104 @@ impl ManagingAgent {
     }
 
     fn create_agents(&mut self) {
-        self.agents.push(Box::new(AgentSolutionsArchitect::new()));
-        self.agents.push(Box::new(AgentBackendDeveloper::new()));
+        self.add_agent(Box::new(AgentSolutionsArchitect::new()));
+        self.add_agent(Box::new(AgentBackendDeveloper::new()));
         // Add backend agent
     }
 
-    pub async fn execute_project(&mut self) {
+    pub async fn execute_project(&mut self) -> Result<(), AgentError> {
         self.create_agents();
+        let mut outcome: Result<(), AgentError> = Ok(());
 
         for agent in &mut self.agents {
-            let agent_res: Result<(), Box<dyn std::error::Error>> =
-                agent.execute(&mut self.factsheet).await;
+            let agent_res: Result<(), AgentError> = with_usage_tracker(
+                self.usage.clone(),
+                with_journal(self.journal.clone(), agent.execute(&mut self.factsheet)),
+            )
+            .await;
+
+            if let Err(e) = agent_res {
+                let agent_info = agent.get_attributes_from_agent();
+                PrintCommand::Issue.print_agent_message(
+                    &self.attributes.position,
+                    format!("{} failed: {}", agent_info.position, e).as_str(),
+                );
+                // Later agents build on the work of earlier ones
+                outcome = Err(e);
+                break;
+            }
+        }
+
+        self.print_report();
+        self.report_usage();
+        self.report_redactions();
+        if let Some(workspace) = &self.factsheet.workspace {
+            PrintCommand::UnitTest.print_agent_message(
+                &self.attributes.position,
+                &format!("Backend workspace: {}", workspace.display()),
+            );
+        }
+        if let Some(journal) = &self.journal {
+            PrintCommand::UnitTest.print_agent_message(
+                &self.attributes.position,
+                &format!("Run journal: {}", journal.path().display()),
+            );
+        }
+        outcome
+    }
+
+    // Token and cost summary, written as JSON to LLM_USAGE_REPORT when set
+    fn report_usage(&self) {
+        self.usage.print_summary(&self.attributes.position);
 
-            let agent_info = agent.get_attributes_from_agent();
+        if let Ok(path) = env::var("LLM_USAGE_REPORT") {
+            if let Err(e) = self.usage.export(&path) {
+                PrintCommand::Issue.print_agent_message(&self.attributes.position, &e.to_string());
+            }
+        }
+    }
+
+    // Secrets kept out of prompts, answers and logs during the run
+    fn report_redactions(&self) {
+        let counts = redactor().counts();
+        if counts.is_empty() {
+            return;
+        }
+        let by_kind: Vec<String> = counts
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        PrintCommand::Issue.print_agent_message(
+            &self.attributes.position,
+            &format!(
+                "Redacted {} secrets: {}",
+                redactor().total(),
+                by_kind.join(", ")
+            ),
+        );
+    }
+
+    // What the run produced, also when it stopped early
+    fn print_report(&self) {
+        let stages: [(&str, bool); 4] = [
+            ("project scope", self.factsheet.project_scope.is_some()),
+            ("external urls", self.factsheet.external_url.is_some()),
+            ("backend code", self.factsheet.backend_code.is_some()),
+            (
+                "api endpoints",
+                self.factsheet.api_endpoint_schema.is_some(),
+            ),
+        ];
+
+        for (stage, done) in stages {
+            match done {
+                true => PrintCommand::UnitTest
+                    .print_agent_message(&self.attributes.position, &format!("{}: done", stage)),
+                false => PrintCommand::Issue.print_agent_message(
+                    &self.attributes.position,
+                    &format!("{}: not produced", stage),
+                ),
+            }
         }
     }
 }
@@ -78,15 +192,60 @@ impl ManagingAgent {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::apis::llm_provider::with_provider;
+    use crate::helpers::test_support::{backend_workspace, mock_openai};
 
     #[tokio::test]
     pub async fn test_managing_agent() {
+        let _workspace = backend_workspace().await;
+        let runs_dir =
+            std::env::temp_dir().join(format!("auto_gippity_runs_{}", std::process::id()));
+        std::env::set_var("LLM_RUNS_DIR", &runs_dir);
         let usr_req= "need a full stack app that fetches and tracks my fitness progress. Need to include timezone from the web".to_string();
-        let mut managin_agent = ManagingAgent::new(usr_req)
-            .await
-            .expect("Unable to create managing agent");
+        // Every call goes over HTTP through the OpenAI provider, streamed and structured ones too
+        let (_server, provider) = mock_openai("managing_agent").await;
+        let managin_agent = with_provider(provider, async {
+            let mut managin_agent = ManagingAgent::new(usr_req)
+                .await
+                .expect("Unable to create managing agent");
+
+            managin_agent
+                .execute_project()
+                .await
+                .expect("Project execution failed");
+            managin_agent
+        })
+        .await;
+
+        let factsheet = &managin_agent.factsheet;
+        assert!(factsheet.project_scope.is_some());
+        assert!(factsheet.backend_code.is_some());
+        // Code was written and built in a workspace of this run, next to its journal
+        let workspace = factsheet.workspace.as_ref().unwrap();
+        assert!(workspace.starts_with(&runs_dir));
+        assert!(workspace.join("src/main.rs").exists());
+        // Only static GET routes are kept for testing
+        assert_eq!(factsheet.api_endpoint_schema.as_ref().unwrap().len(), 2);
+
+        let usage = managin_agent.usage.ledger();
+        assert_eq!(usage.total.calls, 5);
+        assert_eq!(usage.total.prompt_tokens, 5735);
+        assert_eq!(usage.total.completion_tokens, 1965);
+        assert_eq!(usage.by_agent["Project Manager"].calls, 1);
+        assert_eq!(usage.by_function["print_rest_api_endpoints"].calls, 1);
+        assert!((usage.total.cost_usd - 0.0339875).abs() < 1e-9);
 
-        managin_agent.execute_project().await;
+        // Every exchange is in the run journal
+        let journal = managin_agent.journal.as_ref().unwrap();
+        let lines = std::fs::read_to_string(journal.path()).unwrap();
+        let entries: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0]["agent"], "Project Manager");
+        assert_eq!(entries[4]["ai_function"], "print_rest_api_endpoints");
+        assert_eq!(entries[4]["usage"]["prompt_tokens"], 1190);
         dbg!(managin_agent.factsheet);
     }
 }
@@ -1,36 +1,68 @@
 use std::{
-    env,
-    fmt::format,
+    fs,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     time::Duration,
 };
 
-use super::agent_traits::{FactSheet, RouteObject, SpecialFunctions};
+use super::agent_traits::{AiFunction, BackendFramework, FactSheet, RouteObject, SpecialFunctions};
 use crate::{
-    ai_functions::aifunc_backend::{
-        print_backend_webserver_code, print_fixed_code, print_improved_webserver_code,
-        print_rest_api_endpoints,
-    },
+    ai_functions::aifunc_backend::print_fixed_code,
+    error::AgentError,
     helpers::{
+        cargo_diagnostics::{apply_fixes, cargo_build, format_errors, BuildOutput, Diagnostic},
+        code_extract::{extract_files, CodeFile, CodeMap, MAIN_FILE},
         command_line::{confirm_safe_code, PrintCommand},
         general::{
-            ai_task_request, check_status_code, read_code_template_contents,
-            read_exec_main_contents, save_api_endpoint, save_backend_code,
+            ai_task_request_decoded, ai_task_request_streamed, check_status_code,
+            read_code_template_contents, read_exec_main_contents, save_api_endpoint,
+            save_backend_code,
         },
+        prompt_packing::{compiler_error_files, first_compiler_errors, fit_prompt, PromptSection},
     },
-    models::agent_basic::{
-        basic_agent::{AgentState, BasicAgent},
-        basic_traits::BasicTrait,
+    models::{
+        agent_basic::{
+            basic_agent::{AgentState, BasicAgent},
+            basic_traits::BasicTrait,
+        },
+        general::workspace::RunWorkspace,
     },
 };
-use dotenv::dotenv;
 use reqwest::Client;
 use tokio::time;
 
+// Answers without usable code are asked for again this many times in total
+const CODE_ANSWER_ATTEMPTS: u8 = 3;
+
+// Later errors are mostly follow-ups of the first ones
+const MAX_COMPILER_ERRORS: usize = 5;
+
+// The run's backend crate, scaffolded from the template on first use
+fn ensure_workspace(factsheet: &mut FactSheet) -> Result<PathBuf, AgentError> {
+    if let Some(workspace) = &factsheet.workspace {
+        return Ok(workspace.clone());
+    }
+    let workspace: PathBuf = RunWorkspace::from_env(factsheet.framework)?
+        .dir()
+        .to_path_buf();
+    factsheet.workspace = Some(workspace.clone());
+    Ok(workspace)
+}
+
+// Merges the answer into the factsheet's code map and writes the whole tree to the workspace
+fn update_backend_code(factsheet: &mut FactSheet, files: Vec<CodeFile>) -> Result<(), AgentError> {
+    let workspace: PathBuf = ensure_workspace(factsheet)?;
+    let code: &mut CodeMap = factsheet.backend_code.get_or_insert_with(CodeMap::default);
+    code.merge(files);
+    save_backend_code(&workspace, code)
+}
+
 #[derive(Debug)]
 pub struct AgentBackendDeveloper {
     attributes: BasicAgent,
     bug_errors: Option<String>,
+    // Parsed errors of the last build, empty when it failed before compiling
+    build_errors: Vec<Diagnostic>,
     bug_count: u8,
 }
 
@@ -44,78 +76,229 @@ impl AgentBackendDeveloper {
             attributes,
             bug_count: 0,
             bug_errors: None,
+            build_errors: vec![],
         }
     }
 
-    async fn call_initial_backend_code(&mut self, factsheet: &mut FactSheet) {
-        let code_template_str: String = read_code_template_contents();
-
-        let msg_context: String = format!(
-            "CODE TEMPLATE {} \n PROJECT_DESCRIPTION {} \n",
-            code_template_str, factsheet.project_description
-        );
-
-        let ai_response: String = ai_task_request(
-            msg_context,
-            &self.attributes.position,
-            get_function_string!(print_backend_webserver_code),
-            print_backend_webserver_code,
-        )
-        .await;
-        save_backend_code(&ai_response.replace("```rust", "").replace("```", ""));
-        factsheet.backend_code = Some(ai_response);
+    async fn call_initial_backend_code(
+        &mut self,
+        factsheet: &mut FactSheet,
+    ) -> Result<(), AgentError> {
+        let code_template_str: String = read_code_template_contents(factsheet.framework)?;
+
+        let (ai_function, function_pass): AiFunction = factsheet.framework.code_function();
+        let sections: Vec<PromptSection> = vec![
+            PromptSection::new("CODE TEMPLATE", 1, code_template_str),
+            PromptSection::new("PROJECT_DESCRIPTION", 0, &factsheet.project_description),
+        ];
+
+        let files: Vec<CodeFile> = self
+            .request_code(sections, ai_function, function_pass, MAIN_FILE)
+            .await?;
+        update_backend_code(factsheet, files)
     }
 
-    async fn call_improved_backend_code(&mut self, factsheet: &mut FactSheet) {
-        let msg_context: String = format!(
-            "CODE TEMPLATE: {:?} \n PROJECT_DESCRIPTION: {:?} \n",
-            factsheet.backend_code, factsheet
-        );
-
-        let ai_response: String = ai_task_request(
-            msg_context,
-            &self.attributes.position,
-            get_function_string!(print_improved_webserver_code),
-            print_improved_webserver_code,
-        )
-        .await;
+    async fn call_improved_backend_code(
+        &mut self,
+        factsheet: &mut FactSheet,
+    ) -> Result<(), AgentError> {
+        // Only what the code depends on, the rest of the factsheet is left out
+        let (ai_function, function_pass): AiFunction = factsheet.framework.improved_code_function();
+        let sections: Vec<PromptSection> = vec![
+            PromptSection::new(
+                "CODE TEMPLATE",
+                1,
+                factsheet
+                    .backend_code
+                    .as_ref()
+                    .map(CodeMap::render)
+                    .unwrap_or_default(),
+            ),
+            PromptSection::new("PROJECT_DESCRIPTION", 0, &factsheet.project_description),
+            PromptSection::new("PROJECT_SCOPE", 2, format!("{:?}", factsheet.project_scope)),
+            PromptSection::new(
+                "EXTERNAL_URLS",
+                2,
+                format!("{:?}", factsheet.external_url.clone().unwrap_or_default()),
+            ),
+        ];
+
+        let files: Vec<CodeFile> = self
+            .request_code(sections, ai_function, function_pass, MAIN_FILE)
+            .await?;
+        update_backend_code(factsheet, files)
+    }
 
-        save_backend_code(&ai_response.replace("```rust", "").replace("```", ""));
-        factsheet.backend_code = Some(ai_response);
+    async fn call_fix_code_bugs(&mut self, factsheet: &mut FactSheet) -> Result<(), AgentError> {
+        // cargo's own output when the build failed before compiling
+        let (errors, omitted): (String, usize) = match self.build_errors.is_empty() {
+            false => format_errors(&self.build_errors, MAX_COMPILER_ERRORS),
+            true => first_compiler_errors(
+                self.bug_errors.as_deref().unwrap_or_default(),
+                MAX_COMPILER_ERRORS,
+            ),
+        };
+        if omitted > 0 {
+            PrintCommand::Issue.print_agent_message(
+                &self.attributes.position,
+                format!(
+                    "Sending the first {} compiler errors, {} left out",
+                    MAX_COMPILER_ERRORS, omitted
+                )
+                .as_str(),
+            );
+        }
+        // Only the files the errors point at are sent and answered
+        let code: CodeMap = factsheet.backend_code.clone().unwrap_or_default();
+        let broken: CodeMap = code.only(&compiler_error_files(&errors));
+        let unchanged: Vec<&str> = code
+            .paths()
+            .filter(|path| broken.get(path).is_none())
+            .collect();
+        // An answer without file headers belongs to the only file asked for
+        let default_path: String = match broken.paths().collect::<Vec<&str>>()[..] {
+            [path] => path.to_string(),
+            _ => MAIN_FILE.to_string(),
+        };
+
+        let sections: Vec<PromptSection> = vec![
+            PromptSection::new("BROKEN_CODE", 1, broken.render()),
+            PromptSection::new("ERROR_BUGS", 0, errors),
+            PromptSection::new("UNCHANGED_FILES", 2, unchanged.join(", ")),
+            PromptSection::new(
+                "INSTRUCTION",
+                0,
+                "THIS FUNCTION ONLY OUTPUTS CODE. JUST OUTPUT THE CODE OF THE FILES YOU CHANGE.",
+            ),
+        ];
+
+        let files: Vec<CodeFile> = self
+            .request_code(
+                sections,
+                get_function_string!(print_fixed_code),
+                print_fixed_code,
+                &default_path,
+            )
+            .await?;
+        update_backend_code(factsheet, files)
     }
 
-    async fn call_fix_code_bugs(&mut self, factsheet: &mut FactSheet) {
-        let msg_context: String = format!(
-            "BROKEN_CODE {:?} \n ERROR_BUGS {:?} \n
-            THIS FUNCTION ONLY OUTPUTS CODE. JUST OUTPUT THE CODE.
-            ",
-            factsheet.backend_code, self.bug_errors
-        );
+    // Code files from the answer; an answer without code is asked for again with the reason.
+    // Earlier answers are sent along, so a fix can see the attempts that already failed.
+    // The sections are packed for every attempt. Code without a file header is saved as
+    // default_path.
+    async fn request_code(
+        &mut self,
+        mut sections: Vec<PromptSection>,
+        ai_function: &str,
+        function_pass: for<'a> fn(&'a str) -> &'static str,
+        default_path: &str,
+    ) -> Result<Vec<CodeFile>, AgentError> {
+        let mut attempt: u8 = 1;
+        loop {
+            let msg_context: String = fit_prompt(
+                &self.attributes.memory,
+                &self.attributes.position,
+                ai_function,
+                function_pass,
+                sections.clone(),
+            )?;
+            let ai_response: String = ai_task_request_streamed(
+                &mut self.attributes.memory,
+                msg_context,
+                &self.attributes.position,
+                ai_function,
+                function_pass,
+            )
+            .await?;
+
+            let reason: String = match extract_files(&ai_response, "rust", default_path) {
+                Ok(files) => return Ok(files),
+                Err(reason) => reason,
+            };
+            if attempt >= CODE_ANSWER_ATTEMPTS {
+                return Err(AgentError::decode(ai_function, reason, &ai_response));
+            }
+            PrintCommand::Issue.print_agent_message(
+                &self.attributes.position,
+                format!(
+                    "Rejected answer ({} of {}): {}",
+                    attempt, CODE_ANSWER_ATTEMPTS, reason
+                )
+                .as_str(),
+            );
+            attempt += 1;
+            // Replaces the reason of an earlier rejection, the answers themselves are in memory
+            sections.retain(|section| section.label != "PREVIOUS_ANSWER_REJECTED");
+            sections.push(PromptSection::new("PREVIOUS_ANSWER_REJECTED", 0, reason));
+        }
+    }
 
-        let ai_response: String = ai_task_request(
-            msg_context,
+    // Applies rustc's machine-applicable suggestions and builds again, so the model only
+    // gets the errors those do not fix
+    fn apply_compiler_fixes(
+        &self,
+        factsheet: &mut FactSheet,
+        workspace: &Path,
+        build: BuildOutput,
+    ) -> Result<BuildOutput, AgentError> {
+        let changed = apply_fixes(workspace, &build.errors)?;
+        if changed.is_empty() {
+            return Ok(build);
+        }
+        PrintCommand::UnitTest.print_agent_message(
             &self.attributes.position,
-            get_function_string!(print_fixed_code),
-            print_fixed_code,
-        )
-        .await;
+            format!(
+                "Backend code unit testing: applied compiler suggestions to {}",
+                changed.iter().cloned().collect::<Vec<String>>().join(", ")
+            )
+            .as_str(),
+        );
 
-        save_backend_code(&ai_response.replace("```rust", "").replace("```", ""));
-        factsheet.backend_code = Some(ai_response);
+        let mut files: Vec<CodeFile> = vec![];
+        for path in changed {
+            let file_path: PathBuf = workspace.join(&path);
+            let contents: String = fs::read_to_string(&file_path).map_err(AgentError::io(
+                format!("Failed to read backend code {}", file_path.display()),
+            ))?;
+            files.push(CodeFile { path, contents });
+        }
+        factsheet
+            .backend_code
+            .get_or_insert_with(CodeMap::default)
+            .merge(files);
+        cargo_build(workspace)
     }
 
-    async fn call_extract_rest_api_endpoints(&self) -> String {
-        let backend_code = read_exec_main_contents();
+    // Routes can be in any module, so every Rust file of the code map is sent
+    async fn call_extract_rest_api_endpoints(
+        &mut self,
+        framework: BackendFramework,
+        code: Option<&CodeMap>,
+        workspace: &Path,
+    ) -> Result<Vec<RouteObject>, AgentError> {
+        let backend_code: String = match code {
+            Some(code) => {
+                let sources: Vec<CodeFile> = code
+                    .files()
+                    .into_iter()
+                    .filter(|file| file.path.ends_with(".rs"))
+                    .collect();
+                CodeMap::from(sources).render()
+            }
+            None => read_exec_main_contents(workspace)?,
+        };
 
         let msg_context: String = format!("CODE_INPUT: {}", backend_code);
-        let ai_response: String = ai_task_request(
+        let (ai_function, function_pass): AiFunction = framework.endpoints_function();
+        ai_task_request_decoded::<Vec<RouteObject>>(
+            &mut self.attributes.memory,
             msg_context,
             &self.attributes.position,
-            get_function_string!(print_rest_api_endpoints),
-            print_rest_api_endpoints,
+            ai_function,
+            function_pass,
         )
-        .await;
-        ai_response
+        .await
     }
 }
 
@@ -125,22 +308,31 @@ impl SpecialFunctions for AgentBackendDeveloper {
         &self.attributes
     }
 
-    async fn execute(
-        &mut self,
-        factsheet: &mut FactSheet,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn execute(&mut self, factsheet: &mut FactSheet) -> Result<(), AgentError> {
         while self.attributes.state != AgentState::Finished {
             match &self.attributes.state {
                 AgentState::Discovery => {
-                    self.call_initial_backend_code(factsheet).await;
+                    self.call_initial_backend_code(factsheet).await?;
                     self.attributes.state = AgentState::Working;
                 }
                 AgentState::Working => {
-                    if self.bug_count == 0 {
-                        self.call_improved_backend_code(factsheet).await;
+                    let work_res: Result<(), AgentError> = if self.bug_count == 0 {
+                        self.call_improved_backend_code(factsheet).await
                     } else {
-                        self.call_fix_code_bugs(factsheet).await;
+                        self.call_fix_code_bugs(factsheet).await
+                    };
+
+                    // Out of budget: keep the code written so far instead of another fix round
+                    if let Err(AgentError::Budget(exceeded)) = &work_res {
+                        if let Some(code) = factsheet.backend_code.clone() {
+                            save_backend_code(&ensure_workspace(factsheet)?, &code)?;
+                        }
+                        PrintCommand::Issue.print_agent_message(
+                            &self.attributes.position,
+                            format!("Stopping with last backend code: {}", exceeded).as_str(),
+                        );
                     }
+                    work_res?;
                     self.attributes.state = AgentState::UnitTesting;
                     continue;
                 }
@@ -153,49 +345,59 @@ impl SpecialFunctions for AgentBackendDeveloper {
                     let is_safe_code = confirm_safe_code();
 
                     if !is_safe_code {
-                        panic!("Exiting process .....")
+                        return Err(AgentError::UserAbort(
+                            "generated backend code was not approved".to_string(),
+                        ));
                     }
                     PrintCommand::UnitTest.print_agent_message(
                         &self.attributes.position,
                         "Backend code unit testing: building project",
                     );
 
-                    let path = env::var("EXEC_PATH").expect("Code execution path not found");
-                    let build_backend_server: std::process::Output = Command::new("cargo")
-                        .arg("build")
-                        .current_dir(path)
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .output()
-                        .expect("Failed to build backend application");
+                    let workspace: PathBuf = ensure_workspace(factsheet)?;
+                    let mut build: BuildOutput = cargo_build(&workspace)?;
+                    if !build.success {
+                        build = self.apply_compiler_fixes(factsheet, &workspace, build)?;
+                    }
 
-                    if build_backend_server.status.success() {
+                    if build.success {
                         self.bug_count = 0;
                         PrintCommand::UnitTest.print_agent_message(
                             &self.attributes.position,
                             "Backend code unit testing: Test server build successful...",
                         );
                     } else {
-                        let err_arr = build_backend_server.stderr;
-                        let err_str = String::from_utf8(err_arr).unwrap();
+                        let err_str: String = match build.errors.is_empty() {
+                            true => build.stderr,
+                            false => format_errors(&build.errors, usize::MAX).0,
+                        };
 
                         self.bug_count += 1;
-                        self.bug_errors = Some(err_str);
+                        self.bug_errors = Some(err_str.clone());
+                        self.build_errors = build.errors;
 
                         if self.bug_count > 2 {
                             PrintCommand::Issue.print_agent_message(
                                 &self.attributes.position,
                                 "Backend code unit testing: Too many bugs found in code",
                             );
-                            panic!("Error too many bugs");
+                            // Last attempt stays saved on disk and on the factsheet
+                            return Err(AgentError::Build {
+                                attempts: self.bug_count,
+                                errors: err_str,
+                            });
                         }
                         self.attributes.state = AgentState::Working;
                         continue;
                     }
 
-                    let api_endpoint_str: String = self.call_extract_rest_api_endpoints().await;
-                    let api_ep: Vec<RouteObject> = serde_json::from_str(&api_endpoint_str)
-                        .expect("Failed to decode api endpoints");
+                    let api_ep: Vec<RouteObject> = self
+                        .call_extract_rest_api_endpoints(
+                            factsheet.framework,
+                            factsheet.backend_code.as_ref(),
+                            &workspace,
+                        )
+                        .await?;
 
                     let check_eps: Vec<RouteObject> = api_ep
                         .iter()
@@ -212,14 +414,16 @@ impl SpecialFunctions for AgentBackendDeveloper {
                         "Backend code unit testing: Starting web server...",
                     );
 
-                    let path = env::var("EXEC_PATH").expect("Code execution path not found");
+                    // The same for every framework, each starter template binds 127.0.0.1:8080
                     let mut run_backend_server: std::process::Child = Command::new("cargo")
                         .arg("run")
-                        .current_dir(path)
+                        .current_dir(&workspace)
                         .stdout(Stdio::piped())
                         .stderr(Stdio::piped())
                         .spawn()
-                        .expect("Failed to fun backend application");
+                        .map_err(|e| {
+                            AgentError::Runtime(format!("Failed to run backend: {}", e))
+                        })?;
 
                     PrintCommand::UnitTest.print_agent_message(
                         &self.attributes.position,
@@ -232,7 +436,7 @@ impl SpecialFunctions for AgentBackendDeveloper {
                     let client: Client = Client::builder()
                         .timeout(Duration::from_secs(5))
                         .build()
-                        .unwrap();
+                        .map_err(|e| AgentError::Config(e.to_string()))?;
 
                     for ep in check_eps {
                         let testing_msg = format!("Testing endpoint '{}...'", ep.route);
@@ -256,7 +460,7 @@ impl SpecialFunctions for AgentBackendDeveloper {
                                 // kill $(lsof -t -i:8080)
                                 run_backend_server
                                     .kill()
-                                    .expect("Failed to kill backend server");
+                                    .map_err(AgentError::io("Failed to kill backend server"))?;
                                 PrintCommand::Issue.print_agent_message(
                                     &self.attributes.position,
                                     format!("Error checking backend {}", e).as_str(),
@@ -264,14 +468,19 @@ impl SpecialFunctions for AgentBackendDeveloper {
                             }
                         }
                     }
-                    save_api_endpoint(&api_endpoint_str);
+                    let api_endpoint_str: String = serde_json::to_string_pretty(&api_ep)
+                        .map_err(|e| AgentError::Runtime(e.to_string()))?;
+                    save_api_endpoint(&api_endpoint_str)?;
                     PrintCommand::UnitTest.print_agent_message(
                         &self.attributes.position,
                         "Backend code unit testing: testing complete",
                     );
+                    run_backend_server.kill().map_err(AgentError::io(
+                        "Failed to kill backend server on completion",
+                    ))?;
                     run_backend_server
-                        .kill()
-                        .expect("Failed to kill backend server on completion");
+                        .wait()
+                        .map_err(AgentError::io("Failed to wait on backend server"))?;
                     self.attributes.state = AgentState::Finished;
                 }
                 _ => {}
@@ -284,11 +493,32 @@ impl SpecialFunctions for AgentBackendDeveloper {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::apis::llm_provider::with_provider;
+    use crate::helpers::general::env_path;
+    use crate::helpers::test_support::{
+        backend_workspace, fixture_path, replay_answers, replay_cassette,
+    };
+    use crate::models::general::{
+        budget::{Budget, BudgetLimit},
+        usage::{with_usage_tracker, PriceTable, UsageTracker},
+    };
+    use std::{fs, sync::Arc};
+
+    fn fact_sheet(code: Option<CodeMap>) -> FactSheet {
+        FactSheet {
+            project_description: "build a health check server".to_string(),
+            framework: BackendFramework::default(),
+            project_scope: None,
+            external_url: None,
+            backend_code: code,
+            api_endpoint_schema: None,
+            workspace: None,
+        }
+    }
 
     #[tokio::test]
     async fn tests_writing_backend_code() {
-        std::env::set_var("RUST_BACKTRACE", "1");
-        dotenv().ok();
+        let _workspace = backend_workspace().await;
         let mut agent = AgentBackendDeveloper::new();
         let fact_sheet_str: &str = r#"{
     "project_description": "build a website that fetches and tracks fitness progress including timezone from the web.",
@@ -306,10 +536,163 @@ mod tests {
 }"#;
         let mut fact_sheet: FactSheet = serde_json::from_str(fact_sheet_str).unwrap();
         agent.attributes.state = AgentState::UnitTesting;
-        agent
-            .execute(&mut fact_sheet)
-            .await
-            .expect("Failed to execute backend developer agent");
+        with_provider(
+            replay_cassette("writing_backend_code"),
+            agent.execute(&mut fact_sheet),
+        )
+        .await
+        .expect("Failed to execute backend developer agent");
+        assert_eq!(fact_sheet.api_endpoint_schema.as_ref().unwrap().len(), 1);
         dbg!(fact_sheet);
     }
+
+    #[tokio::test]
+    async fn tests_fix_loop_stops_when_out_of_budget() {
+        let _workspace = backend_workspace().await;
+        let mut agent = AgentBackendDeveloper::new();
+        agent.attributes.state = AgentState::Working;
+        agent.bug_count = 1;
+        agent.bug_errors = Some("error[E0425]: cannot find value".to_string());
+
+        let mut fact_sheet = fact_sheet(Some(CodeMap::from(vec![CodeFile {
+            path: MAIN_FILE.to_string(),
+            contents: "fn main() {}\n".to_string(),
+        }])));
+
+        let mut budget = Budget::default();
+        budget.per_agent.max_calls = Some(0);
+        let tracker = Arc::new(UsageTracker::new(PriceTable::default(), budget));
+        // The prompt is sized for the provider's model before the budget refuses the call
+        let res = with_provider(
+            replay_answers(&[]),
+            with_usage_tracker(tracker, agent.execute(&mut fact_sheet)),
+        )
+        .await;
+
+        match res {
+            Err(AgentError::Budget(exceeded)) => assert_eq!(exceeded.limit, BudgetLimit::Calls),
+            other => panic!("expected budget error, got {:?}", other),
+        }
+        assert!(fact_sheet.backend_code.is_some());
+        let workspace = fact_sheet.workspace.unwrap();
+        let saved = fs::read_to_string(workspace.join("src/main.rs"));
+        assert_eq!(saved.unwrap(), "fn main() {}\n");
+        // The template crate is left alone
+        let template_main =
+            fs::read_to_string(format!("{}/main.rs", env_path("TEMPLATE_PATH").unwrap()));
+        assert_ne!(template_main.unwrap(), "fn main() {}\n");
+    }
+
+    #[tokio::test]
+    async fn tests_answer_without_code_is_asked_again() {
+        let _workspace = backend_workspace().await;
+        let mut agent = AgentBackendDeveloper::new();
+        let mut fact_sheet = fact_sheet(None);
+
+        with_provider(
+            replay_answers(&[
+                "I would need more details about the server first.",
+                "Here you go:\n```rust\n// file: src/main.rs\nmod routes;\nfn main() {}\n```\n```rust\n// file: src/routes.rs\npub fn health() {}\n```\nThe health route lives in routes.rs.",
+            ]),
+            agent.call_initial_backend_code(&mut fact_sheet),
+        )
+        .await
+        .unwrap();
+
+        // The rejected answer stays in memory and is sent with the retry
+        assert_eq!(agent.attributes.memory.len(), 4);
+        assert_eq!(
+            agent.attributes.memory[1].content,
+            "I would need more details about the server first."
+        );
+        // The retry is packed again with the reason, not stacked on the first prompt
+        let retry = &agent.attributes.memory[2].content;
+        assert_eq!(retry.matches("build a health check server").count(), 1);
+        assert!(retry.contains("PREVIOUS_ANSWER_REJECTED: the answer contains no rust code"));
+
+        let src_dir = fact_sheet.workspace.as_ref().unwrap().join("src");
+        let main = fs::read_to_string(src_dir.join("main.rs")).unwrap();
+        assert_eq!(main, "mod routes;\nfn main() {}\n");
+        let routes = fs::read_to_string(src_dir.join("routes.rs")).unwrap();
+        assert_eq!(routes, "pub fn health() {}\n");
+        let code = fact_sheet.backend_code.unwrap();
+        assert_eq!(
+            code.paths().collect::<Vec<_>>(),
+            vec!["src/main.rs", "src/routes.rs"]
+        );
+    }
+
+    #[tokio::test]
+    async fn tests_fix_only_sends_and_replaces_files_with_errors() {
+        let _workspace = backend_workspace().await;
+        let mut agent = AgentBackendDeveloper::new();
+        agent.bug_errors = Some(
+            "error[E0308]: mismatched types\n --> src/routes.rs:1:30\n\n\
+error: could not compile `backend` due to 1 previous error\n"
+                .to_string(),
+        );
+        let mut fact_sheet = fact_sheet(Some(CodeMap::from(vec![
+            CodeFile {
+                path: MAIN_FILE.to_string(),
+                contents: "mod routes;\nfn main() {}\n".to_string(),
+            },
+            CodeFile {
+                path: "src/routes.rs".to_string(),
+                contents: "pub fn health() -> u16 { \"ok\" }\n".to_string(),
+            },
+        ])));
+
+        with_provider(
+            replay_answers(&["```rust\npub fn health() -> u16 { 200 }\n```"]),
+            agent.call_fix_code_bugs(&mut fact_sheet),
+        )
+        .await
+        .unwrap();
+
+        let prompt = &agent.attributes.memory[0].content;
+        assert!(prompt.contains("pub fn health() -> u16 { \"ok\" }"));
+        assert!(!prompt.contains("mod routes;"));
+        assert!(prompt.contains("UNCHANGED_FILES: src/main.rs"));
+
+        // The headerless answer is the fixed routes.rs, main.rs is kept
+        let code = fact_sheet.backend_code.as_ref().unwrap();
+        assert_eq!(
+            code.get("src/routes.rs"),
+            Some("pub fn health() -> u16 { 200 }\n")
+        );
+        let src_dir = fact_sheet.workspace.as_ref().unwrap().join("src");
+        let main = fs::read_to_string(src_dir.join("main.rs")).unwrap();
+        assert_eq!(main, "mod routes;\nfn main() {}\n");
+        let routes = fs::read_to_string(src_dir.join("routes.rs")).unwrap();
+        assert_eq!(routes, "pub fn health() -> u16 { 200 }\n");
+    }
+
+    #[tokio::test]
+    async fn tests_compiler_suggestions_are_applied_before_asking() {
+        let _workspace = backend_workspace().await;
+        let agent = AgentBackendDeveloper::new();
+        let mut fact_sheet = fact_sheet(None);
+        let workspace = ensure_workspace(&mut fact_sheet).unwrap();
+        fs::copy(
+            fixture_path("cargo_diagnostics/main.rs"),
+            workspace.join("src/main.rs"),
+        )
+        .unwrap();
+
+        let build = cargo_build(&workspace).unwrap();
+        assert!(!build.success);
+        assert_eq!(build.errors.len(), 4);
+        let build = agent
+            .apply_compiler_fixes(&mut fact_sheet, &workspace, build)
+            .unwrap();
+
+        // Only what the model has to fix is left
+        let codes: Vec<Option<&str>> = build.errors.iter().map(|e| e.code.as_deref()).collect();
+        assert_eq!(codes, vec![Some("E0432"), Some("E0425")]);
+        let code = fact_sheet.backend_code.unwrap();
+        assert!(code
+            .get(MAIN_FILE)
+            .unwrap()
+            .contains("let a = takes(&s.clone());"));
+    }
 }
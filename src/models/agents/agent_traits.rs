@@ -1,7 +1,87 @@
+use dotenv::dotenv;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::{env, path::PathBuf, str::FromStr};
+use strum_macros::{Display, EnumString};
 
+use crate::ai_functions::aifunc_backend::{
+    print_axum_rest_api_endpoints, print_axum_webserver_code, print_backend_webserver_code,
+    print_improved_axum_webserver_code, print_improved_webserver_code, print_rest_api_endpoints,
+};
+use crate::error::AgentError;
+use crate::helpers::code_extract::CodeMap;
 use crate::models::agent_basic::basic_agent::BasicAgent;
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+
+// ai_function name and the function itself
+pub type AiFunction = (&'static str, for<'a> fn(&'a str) -> &'static str);
+
+// Web framework of the generated backend, picks its starter template and prompts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, EnumString, Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+pub enum BackendFramework {
+    #[default]
+    ActixWeb,
+    Axum,
+}
+
+impl BackendFramework {
+    // BACKEND_FRAMEWORK, default actix-web
+    pub fn from_env() -> Result<Self, AgentError> {
+        dotenv().ok();
+        match env::var("BACKEND_FRAMEWORK") {
+            Ok(value) => Self::from_str(value.trim()).map_err(|_| {
+                AgentError::Config(format!(
+                    "BACKEND_FRAMEWORK: {} is not one of actix-web, axum",
+                    value
+                ))
+            }),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn code_function(&self) -> AiFunction {
+        match self {
+            Self::ActixWeb => (
+                get_function_string!(print_backend_webserver_code),
+                print_backend_webserver_code,
+            ),
+            Self::Axum => (
+                get_function_string!(print_axum_webserver_code),
+                print_axum_webserver_code,
+            ),
+        }
+    }
+
+    pub fn improved_code_function(&self) -> AiFunction {
+        match self {
+            Self::ActixWeb => (
+                get_function_string!(print_improved_webserver_code),
+                print_improved_webserver_code,
+            ),
+            Self::Axum => (
+                get_function_string!(print_improved_axum_webserver_code),
+                print_improved_axum_webserver_code,
+            ),
+        }
+    }
+
+    // Route syntax differs, ":id" in axum and "{id}" in actix-web
+    pub fn endpoints_function(&self) -> AiFunction {
+        match self {
+            Self::ActixWeb => (
+                get_function_string!(print_rest_api_endpoints),
+                print_rest_api_endpoints,
+            ),
+            Self::Axum => (
+                get_function_string!(print_axum_rest_api_endpoints),
+                print_axum_rest_api_endpoints,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct RouteObject {
     pub is_route_dynamic: String,
     pub method: String,
@@ -10,7 +90,7 @@ pub struct RouteObject {
     pub route: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct ProjectScope {
     pub is_crud_required: bool,
     pub is_user_login_and_logout: bool,
@@ -20,17 +100,19 @@ pub struct ProjectScope {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct FactSheet {
     pub project_description: String,
+    #[serde(default)]
+    pub framework: BackendFramework,
     pub project_scope: Option<ProjectScope>,
     pub external_url: Option<Vec<String>>,
-    pub backend_code: Option<String>,
+    pub backend_code: Option<CodeMap>,
     pub api_endpoint_schema: Option<Vec<RouteObject>>,
+    // Run-specific backend crate the code is written to and built in
+    #[serde(default)]
+    pub workspace: Option<PathBuf>,
 }
 
 #[async_trait::async_trait]
 pub trait SpecialFunctions: std::fmt::Debug {
     fn get_attributes_from_agent(&self) -> &BasicAgent;
-    async fn execute(
-        &mut self,
-        factsheet: &mut FactSheet,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+    async fn execute(&mut self, factsheet: &mut FactSheet) -> Result<(), AgentError>;
 }
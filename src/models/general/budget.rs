@@ -0,0 +1,175 @@
+use dotenv::dotenv;
+use std::{env, fmt, time::Duration};
+
+use crate::error::AgentError;
+use crate::models::general::usage::UsageTotals;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    Tokens,
+    Cost,
+    Calls,
+    WallClock,
+}
+
+impl fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tokens => write!(f, "token"),
+            Self::Cost => write!(f, "cost"),
+            Self::Calls => write!(f, "llm call"),
+            Self::WallClock => write!(f, "wall-clock"),
+        }
+    }
+}
+
+// Which cap tripped, for whom ("run" or an agent position), and how far it got
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub scope: String,
+    pub limit: BudgetLimit,
+    pub used: String,
+    pub cap: String,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} budget exhausted ({} of {})",
+            self.scope, self.limit, self.used, self.cap
+        )
+    }
+}
+
+// Caps for one scope, None means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BudgetLimits {
+    pub max_tokens: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+    pub max_calls: Option<u32>,
+    pub max_duration: Option<Duration>,
+}
+
+impl BudgetLimits {
+    // Reads <prefix>_TOKENS, <prefix>_USD, <prefix>_CALLS and <prefix>_SECS
+    fn from_env(prefix: &str) -> Result<Self, AgentError> {
+        fn var<T: std::str::FromStr>(name: String) -> Result<Option<T>, AgentError> {
+            match env::var(&name) {
+                Ok(value) => value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| AgentError::Config(format!("{} is not a number", name))),
+                Err(_) => Ok(None),
+            }
+        }
+
+        Ok(Self {
+            max_tokens: var(format!("{}_TOKENS", prefix))?,
+            max_cost_usd: var(format!("{}_USD", prefix))?,
+            max_calls: var(format!("{}_CALLS", prefix))?,
+            max_duration: var::<u64>(format!("{}_SECS", prefix))?.map(Duration::from_secs),
+        })
+    }
+
+    // A cap counts as hit once reached, since the next call could only go over it
+    pub fn check(
+        &self,
+        scope: &str,
+        totals: &UsageTotals,
+        elapsed: Duration,
+    ) -> Result<(), BudgetExceeded> {
+        let exceeded = |limit: BudgetLimit, used: String, cap: String| BudgetExceeded {
+            scope: scope.to_string(),
+            limit,
+            used,
+            cap,
+        };
+
+        if let Some(cap) = self.max_calls.filter(|cap| totals.calls >= *cap) {
+            return Err(exceeded(
+                BudgetLimit::Calls,
+                totals.calls.to_string(),
+                cap.to_string(),
+            ));
+        }
+        if let Some(cap) = self.max_tokens.filter(|cap| totals.total_tokens() >= *cap) {
+            return Err(exceeded(
+                BudgetLimit::Tokens,
+                totals.total_tokens().to_string(),
+                cap.to_string(),
+            ));
+        }
+        if let Some(cap) = self.max_cost_usd.filter(|cap| totals.cost_usd >= *cap) {
+            return Err(exceeded(
+                BudgetLimit::Cost,
+                format!("${:.4}", totals.cost_usd),
+                format!("${:.4}", cap),
+            ));
+        }
+        if let Some(cap) = self.max_duration.filter(|cap| elapsed >= *cap) {
+            return Err(exceeded(
+                BudgetLimit::WallClock,
+                format!("{}s", elapsed.as_secs()),
+                format!("{}s", cap.as_secs()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Caps for the whole run and for every single agent, checked before each llm call
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget {
+    pub run: BudgetLimits,
+    pub per_agent: BudgetLimits,
+}
+
+impl Budget {
+    // LLM_BUDGET_* caps the run, LLM_AGENT_BUDGET_* each agent
+    pub fn from_env() -> Result<Self, AgentError> {
+        dotenv().ok();
+        Ok(Self {
+            run: BudgetLimits::from_env("LLM_BUDGET")?,
+            per_agent: BudgetLimits::from_env("LLM_AGENT_BUDGET")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_limits_report_first_cap_reached() {
+        let limits = BudgetLimits {
+            max_tokens: Some(1000),
+            max_cost_usd: Some(0.5),
+            ..BudgetLimits::default()
+        };
+        let mut totals = UsageTotals {
+            calls: 4,
+            prompt_tokens: 600,
+            completion_tokens: 300,
+            cost_usd: 0.1,
+            unpriced_calls: 0,
+            cache_hits: 0,
+        };
+        assert!(limits.check("run", &totals, Duration::ZERO).is_ok());
+
+        totals.completion_tokens = 400;
+        let err = limits.check("run", &totals, Duration::ZERO).unwrap_err();
+        assert_eq!(err.limit, BudgetLimit::Tokens);
+        assert_eq!(err.to_string(), "run token budget exhausted (1000 of 1000)");
+
+        let limits = BudgetLimits {
+            max_duration: Some(Duration::from_secs(60)),
+            ..BudgetLimits::default()
+        };
+        let err = limits
+            .check("Backend developer", &totals, Duration::from_secs(61))
+            .unwrap_err();
+        assert_eq!(err.limit, BudgetLimit::WallClock);
+    }
+}
@@ -0,0 +1,177 @@
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::AgentError;
+use crate::models::general::llm::{Message, TokenUsage};
+
+// One llm exchange, a line of journal.jsonl
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JournalEntry {
+    // Unix time in milliseconds when the request was sent
+    pub started_at: u64,
+    pub agent: String,
+    pub ai_function: String,
+    // Library prompt version, or "builtin" for the compiled-in ai_function
+    #[serde(default)]
+    pub prompt_version: String,
+    pub provider: String,
+    pub model: String,
+    pub messages: Vec<Message>,
+    // Raw answer, None when the call failed
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub usage: Option<TokenUsage>,
+    pub retries: u32,
+    pub cached: bool,
+}
+
+// Every llm exchange of a run as JSON Lines in <LLM_RUNS_DIR>/run-<unix secs>-<pid>/journal.jsonl,
+// LLM_RUNS_DIR defaults to "runs". LLM_JOURNAL=0 turns it off.
+#[derive(Debug)]
+pub struct RunJournal {
+    dir: PathBuf,
+    file: Mutex<File>,
+}
+
+impl RunJournal {
+    pub fn create(runs_dir: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let dir: PathBuf = runs_dir.as_ref().join(run_name());
+        fs::create_dir_all(&dir).map_err(AgentError::io(format!(
+            "Failed to create run directory {}",
+            dir.display()
+        )))?;
+
+        let path: PathBuf = dir.join("journal.jsonl");
+        let file: File = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(AgentError::io(format!(
+                "Failed to open run journal {}",
+                path.display()
+            )))?;
+
+        Ok(Self {
+            dir,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn from_env() -> Result<Option<Self>, AgentError> {
+        dotenv().ok();
+        let journal_off: bool = env::var("LLM_JOURNAL")
+            .is_ok_and(|value| matches!(value.trim(), "0" | "false" | "off"));
+        if journal_off {
+            return Ok(None);
+        }
+        let runs_dir: String = env::var("LLM_RUNS_DIR").unwrap_or_else(|_| "runs".to_string());
+        Self::create(runs_dir).map(Some)
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("journal.jsonl")
+    }
+
+    pub fn record(&self, entry: &JournalEntry) -> Result<(), AgentError> {
+        let line: String = serde_json::to_string(entry)
+            .map_err(|e| AgentError::Config(format!("run journal: {}", e)))?;
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)
+            .and_then(|_| file.flush())
+            .map_err(AgentError::io("Failed to write run journal"))
+    }
+}
+
+// run-<unix secs>-<pid>, fixed at first use so the journal and the backend workspace
+// of a run share a directory
+pub fn run_name() -> &'static str {
+    static RUN_NAME: OnceLock<String> = OnceLock::new();
+    RUN_NAME.get_or_init(|| format!("run-{}-{}", unix_millis() / 1000, std::process::id()))
+}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+tokio::task_local! {
+    static RUN_JOURNAL: Arc<RunJournal>;
+}
+
+// Every llm exchange made inside `fut` is written to `journal`, if there is one
+pub async fn with_journal<F: Future>(journal: Option<Arc<RunJournal>>, fut: F) -> F::Output {
+    match journal {
+        Some(journal) => RUN_JOURNAL.scope(journal, fut).await,
+        None => fut.await,
+    }
+}
+
+// No-op outside of with_journal
+pub fn record_exchange(entry: &JournalEntry) -> Result<(), AgentError> {
+    RUN_JOURNAL
+        .try_with(|journal| journal.record(entry))
+        .unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ai_function: &str) -> JournalEntry {
+        JournalEntry {
+            started_at: unix_millis(),
+            agent: "Backend developer".to_string(),
+            ai_function: ai_function.to_string(),
+            prompt_version: "builtin".to_string(),
+            provider: "replay".to_string(),
+            model: "cassette".to_string(),
+            messages: vec![Message {
+                role: "system".to_string(),
+                content: "FUNCTION print_fixed_code".to_string(),
+            }],
+            response: Some("fn main() {}".to_string()),
+            error: None,
+            latency_ms: 3,
+            usage: None,
+            retries: 1,
+            cached: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn tests_exchanges_are_appended_as_json_lines() {
+        let runs_dir = env::temp_dir().join(format!("auto_gippity_journal_{}", std::process::id()));
+        let journal = Arc::new(RunJournal::create(&runs_dir).unwrap());
+        assert!(journal.path().starts_with(&runs_dir));
+
+        with_journal(Some(journal.clone()), async {
+            record_exchange(&entry("print_backend_webserver_code")).unwrap();
+            record_exchange(&entry("print_fixed_code")).unwrap();
+        })
+        .await;
+        // Outside of a scope nothing is written
+        record_exchange(&entry("print_rest_api_endpoints")).unwrap();
+
+        let contents = fs::read_to_string(journal.path()).unwrap();
+        let entries: Vec<JournalEntry> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].ai_function, "print_fixed_code");
+        assert_eq!(entries[1].messages, entry("print_fixed_code").messages);
+        fs::remove_dir_all(runs_dir).unwrap();
+    }
+}
@@ -1,16 +1,32 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
     pub role: String,
     pub content: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatCompletion {
     pub model: String,
     pub messages: Vec<Message>,
     pub temperature: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    // {"type": "json_schema", ...} for structured output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamOptions {
+    pub include_usage: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,17 +39,198 @@ pub struct APIChoice {
     pub message: APIMessage,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct APIUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct APIResponse {
     pub choices: Vec<APIChoice>,
+    #[serde(default)]
+    pub usage: Option<APIUsage>,
+}
+
+// GET /models
+#[derive(Debug, Deserialize)]
+pub struct APIModelList {
+    pub data: Vec<APIModel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct APIModel {
+    pub id: String,
+}
+
+// One `data:` chunk of a streamed chat completion, usage only comes with the last one
+#[derive(Debug, Deserialize)]
+pub struct APIStreamChunk {
+    #[serde(default)]
+    pub choices: Vec<APIStreamChoice>,
+    #[serde(default)]
+    pub usage: Option<APIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct APIStreamChoice {
+    pub delta: APIStreamDelta,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct APIStreamDelta {
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+// Anthropic Messages API
+#[derive(Debug, Serialize, Clone)]
+pub struct ANTRequest {
+    pub model: String,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub messages: Vec<Message>,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    // A single forced tool carries the output schema of structured calls
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+}
+
+// Streamed events only carry the counts known so far
+#[derive(Debug, Deserialize)]
+pub struct ANTUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+}
+
+impl ANTUsage {
+    pub fn token_usage(&self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.input_tokens,
+            completion_tokens: self.output_tokens,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ANTResponse {
     pub content: Vec<ANTContent>,
+    #[serde(default)]
+    pub usage: Option<ANTUsage>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ANTContent {
+    #[serde(default)]
     pub text: String,
+    // Arguments of a tool_use block
+    #[serde(default)]
+    pub input: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ANTStreamMessage {
+    #[serde(default)]
+    pub usage: Option<ANTUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ANTStreamDelta {
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ANTStreamError {
+    pub message: String,
+}
+
+// Events of a streamed Messages API response
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ANTStreamEvent {
+    MessageStart {
+        message: ANTStreamMessage,
+    },
+    ContentBlockDelta {
+        delta: ANTStreamDelta,
+    },
+    MessageDelta {
+        #[serde(default)]
+        usage: Option<ANTUsage>,
+    },
+    MessageStop,
+    Error {
+        error: ANTStreamError,
+    },
+    // ping, content_block_start, content_block_stop
+    #[serde(other)]
+    Other,
+}
+
+// Ollama chat API
+#[derive(Debug, Serialize, Clone)]
+pub struct OllamaOptions {
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    pub stream: bool,
+    pub options: OllamaOptions,
+    // JSON schema the answer must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<serde_json::Value>,
+}
+
+// GET /api/tags, the locally pulled models
+#[derive(Debug, Deserialize)]
+pub struct OllamaTags {
+    pub models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OllamaModel {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OllamaChatResponse {
+    pub message: APIMessage,
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+}
+
+// Tokens billed for one call, whatever the provider calls them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+// Completion text plus what it cost, returned by every provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmResponse {
+    pub content: String,
+    pub model: String,
+    pub usage: Option<TokenUsage>,
+    // Served from the response cache, nothing was billed
+    pub cached: bool,
 }
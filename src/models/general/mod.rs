@@ -1 +1,7 @@
+pub mod budget;
+pub mod journal;
 pub mod llm;
+pub mod prompt_eval;
+pub mod starter_templates;
+pub mod usage;
+pub mod workspace;
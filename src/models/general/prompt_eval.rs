@@ -0,0 +1,485 @@
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use crate::ai_functions::{
+    aifunc_architext::{print_project_scope, print_site_urls},
+    aifunc_backend::{
+        print_axum_rest_api_endpoints, print_axum_webserver_code, print_backend_webserver_code,
+        print_fixed_code, print_improved_axum_webserver_code, print_improved_webserver_code,
+        print_rest_api_endpoints,
+    },
+};
+use crate::error::AgentError;
+use crate::helpers::code_extract::{extract_code, CodeFile, CodeMap};
+use crate::helpers::command_line::PrintCommand;
+use crate::helpers::general::{ai_task_request, ai_task_request_decoded, save_backend_code};
+use crate::helpers::prompt_library::{prompt_library, with_prompt_versions};
+use crate::models::agents::agent_traits::{ProjectScope, RouteObject};
+use crate::models::general::journal::{with_journal, RunJournal};
+
+const EVAL_POSITION: &str = "Prompt Evaluator";
+
+// One stored input, inline or read from a file next to the suite
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    #[serde(default)]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub input_file: Option<PathBuf>,
+}
+
+// Stored inputs for one ai_function and the two prompt versions to compare on them, as TOML.
+// Code answers are built in a scratch copy of `build_crate` (default EXEC_PATH).
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvalSuite {
+    pub ai_function: String,
+    pub versions: Vec<String>,
+    #[serde(default)]
+    pub build_crate: Option<PathBuf>,
+    pub cases: Vec<EvalCase>,
+    // Directory input_file and build_crate are relative to
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+impl EvalSuite {
+    pub fn from_file(path: &Path) -> Result<Self, AgentError> {
+        let contents: String = fs::read_to_string(path).map_err(AgentError::io(format!(
+            "Failed to read eval suite {}",
+            path.display()
+        )))?;
+        let mut suite: Self = toml::from_str(&contents)
+            .map_err(|e| AgentError::Config(format!("eval suite {}: {}", path.display(), e)))?;
+        suite.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(suite)
+    }
+
+    fn input(&self, case: &EvalCase) -> Result<String, AgentError> {
+        match (&case.input, &case.input_file) {
+            (Some(input), None) => Ok(input.clone()),
+            (None, Some(file)) => {
+                let path: PathBuf = self.base_dir.join(file);
+                fs::read_to_string(&path).map_err(AgentError::io(format!(
+                    "Failed to read eval input {}",
+                    path.display()
+                )))
+            }
+            _ => Err(AgentError::Config(format!(
+                "eval case {}: set exactly one of input and input_file",
+                case.name
+            ))),
+        }
+    }
+
+    fn build_crate(&self) -> Result<PathBuf, AgentError> {
+        match &self.build_crate {
+            Some(dir) => Ok(self.base_dir.join(dir)),
+            None => env::var("EXEC_PATH")
+                .map(PathBuf::from)
+                .map_err(|_| AgentError::Config("build_crate or EXEC_PATH not set".to_string())),
+        }
+    }
+}
+
+// How an ai_function's answers are scored
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EvalKind {
+    // JSON decoded through ai_task_request_decoded
+    Decoded,
+    // Rust code that has to build
+    Code,
+}
+
+fn eval_kind(ai_function: &str) -> Option<EvalKind> {
+    match ai_function {
+        "print_project_scope"
+        | "print_site_urls"
+        | "print_rest_api_endpoints"
+        | "print_axum_rest_api_endpoints" => Some(EvalKind::Decoded),
+        _ => code_function(ai_function).map(|_| EvalKind::Code),
+    }
+}
+
+// ai_functions answering with code, for every framework
+fn code_function(ai_function: &str) -> Option<for<'a> fn(&'a str) -> &'static str> {
+    let function_pass: for<'a> fn(&'a str) -> &'static str = match ai_function {
+        "print_backend_webserver_code" => print_backend_webserver_code,
+        "print_improved_webserver_code" => print_improved_webserver_code,
+        "print_axum_webserver_code" => print_axum_webserver_code,
+        "print_improved_axum_webserver_code" => print_improved_axum_webserver_code,
+        "print_fixed_code" => print_fixed_code,
+        _ => return None,
+    };
+    Some(function_pass)
+}
+
+// The first answer to a decoded request, before any repair
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FirstAnswer {
+    pub valid_json: bool,
+    pub schema_ok: bool,
+}
+
+tokio::task_local! {
+    static FIRST_ANSWER: Cell<Option<FirstAnswer>>;
+}
+
+// No-op outside of an eval run
+pub fn record_first_answer(answer: FirstAnswer) {
+    let _ = FIRST_ANSWER.try_with(|first| first.set(Some(answer)));
+}
+
+// Counts over the suite's cases for one prompt version, None where a score does not
+// apply to the ai_function
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VersionScore {
+    pub version: String,
+    pub cases: usize,
+    pub answered: usize,
+    pub valid_json: Option<usize>,
+    pub schema_ok: Option<usize>,
+    // Decoded after repairs, or code extracted
+    pub usable: usize,
+    pub built: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct CaseOutcome {
+    answered: bool,
+    first: Option<FirstAnswer>,
+    usable: bool,
+    built: bool,
+}
+
+async fn decoded_case<T: DeserializeOwned + JsonSchema>(
+    input: String,
+    ai_function: &str,
+    function_pass: for<'a> fn(&'a str) -> &'static str,
+) -> Result<CaseOutcome, AgentError> {
+    FIRST_ANSWER
+        .scope(Cell::new(None), async {
+            let result = ai_task_request_decoded::<T>(
+                &mut vec![],
+                input,
+                EVAL_POSITION,
+                ai_function,
+                function_pass,
+            )
+            .await;
+            let first: Option<FirstAnswer> = FIRST_ANSWER.with(Cell::get);
+            if let Err(e) = &result {
+                PrintCommand::Issue.print_agent_message(EVAL_POSITION, &e.to_string());
+            }
+            Ok(CaseOutcome {
+                answered: first.is_some(),
+                first,
+                usable: result.is_ok(),
+                built: false,
+            })
+        })
+        .await
+}
+
+async fn code_case(
+    input: String,
+    ai_function: &str,
+    function_pass: for<'a> fn(&'a str) -> &'static str,
+    build_crate: &Path,
+) -> Result<CaseOutcome, AgentError> {
+    let answer: String = match ai_task_request(
+        &mut vec![],
+        input,
+        EVAL_POSITION,
+        ai_function,
+        function_pass,
+    )
+    .await
+    {
+        Ok(answer) => answer,
+        Err(e) => {
+            PrintCommand::Issue.print_agent_message(EVAL_POSITION, &e.to_string());
+            return Ok(CaseOutcome::default());
+        }
+    };
+    let files: Vec<CodeFile> = match extract_code(&answer, "rust") {
+        Ok(files) => files,
+        Err(reason) => {
+            PrintCommand::Issue.print_agent_message(EVAL_POSITION, &reason);
+            return Ok(CaseOutcome {
+                answered: true,
+                ..Default::default()
+            });
+        }
+    };
+    Ok(CaseOutcome {
+        answered: true,
+        first: None,
+        usable: true,
+        built: build_in_scratch(build_crate, files)?,
+    })
+}
+
+// Builds the files in a copy of the crate's manifest, the original crate is left alone.
+// The target directory is kept between cases.
+fn build_in_scratch(build_crate: &Path, files: Vec<CodeFile>) -> Result<bool, AgentError> {
+    let scratch: PathBuf =
+        env::temp_dir().join(format!("auto_gippity_eval_{}", std::process::id()));
+    let crate_dir: PathBuf = scratch.join("crate");
+    if crate_dir.exists() {
+        fs::remove_dir_all(&crate_dir).map_err(AgentError::io(format!(
+            "Failed to clear {}",
+            crate_dir.display()
+        )))?;
+    }
+    fs::create_dir_all(crate_dir.join("src")).map_err(AgentError::io(format!(
+        "Failed to create {}",
+        crate_dir.display()
+    )))?;
+
+    for manifest in ["Cargo.toml", "Cargo.lock"] {
+        let source: PathBuf = build_crate.join(manifest);
+        if source.exists() {
+            fs::copy(&source, crate_dir.join(manifest)).map_err(AgentError::io(format!(
+                "Failed to copy {}",
+                source.display()
+            )))?;
+        }
+    }
+    save_backend_code(&crate_dir, &CodeMap::from(files))?;
+
+    let status = Command::new("cargo")
+        .arg("build")
+        .current_dir(&crate_dir)
+        .env("CARGO_TARGET_DIR", scratch.join("target"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(AgentError::io("Failed to build eval answer"))?;
+    Ok(status.success())
+}
+
+// Every case of the suite with `version` selected for the suite's ai_function
+pub async fn score_version(suite: &EvalSuite, version: &str) -> Result<VersionScore, AgentError> {
+    let kind: EvalKind = eval_kind(&suite.ai_function).ok_or_else(|| {
+        AgentError::Config(format!("eval does not support {}", suite.ai_function))
+    })?;
+    let versions: BTreeMap<String, String> =
+        BTreeMap::from([(suite.ai_function.clone(), version.to_string())]);
+
+    with_prompt_versions(versions, async {
+        let mut score: VersionScore = VersionScore {
+            version: version.to_string(),
+            cases: suite.cases.len(),
+            valid_json: (kind == EvalKind::Decoded).then_some(0),
+            schema_ok: (kind == EvalKind::Decoded).then_some(0),
+            built: (kind == EvalKind::Code).then_some(0),
+            ..Default::default()
+        };
+
+        for case in &suite.cases {
+            let msg: String = format!("{} {}: {}", suite.ai_function, version, case.name);
+            PrintCommand::UnitTest.print_agent_message(EVAL_POSITION, &msg);
+            let input: String = suite.input(case)?;
+            let ai_function: &str = &suite.ai_function;
+
+            let outcome: CaseOutcome = match ai_function {
+                "print_project_scope" => {
+                    decoded_case::<ProjectScope>(input, ai_function, print_project_scope).await?
+                }
+                "print_site_urls" => {
+                    decoded_case::<Vec<String>>(input, ai_function, print_site_urls).await?
+                }
+                "print_rest_api_endpoints" => {
+                    decoded_case::<Vec<RouteObject>>(input, ai_function, print_rest_api_endpoints)
+                        .await?
+                }
+                "print_axum_rest_api_endpoints" => {
+                    decoded_case::<Vec<RouteObject>>(
+                        input,
+                        ai_function,
+                        print_axum_rest_api_endpoints,
+                    )
+                    .await?
+                }
+                _ => {
+                    let build_crate: PathBuf = suite.build_crate()?;
+                    let function_pass = code_function(ai_function).unwrap_or(print_fixed_code);
+                    code_case(input, ai_function, function_pass, &build_crate).await?
+                }
+            };
+
+            score.answered += outcome.answered as usize;
+            score.usable += outcome.usable as usize;
+            if let Some(first) = outcome.first {
+                score.valid_json = score.valid_json.map(|n| n + first.valid_json as usize);
+                score.schema_ok = score.schema_ok.map(|n| n + first.schema_ok as usize);
+            }
+            score.built = score.built.map(|n| n + outcome.built as usize);
+        }
+        Ok(score)
+    })
+    .await
+}
+
+pub async fn run_suite(suite: &EvalSuite) -> Result<Vec<VersionScore>, AgentError> {
+    if suite.versions.len() != 2 {
+        return Err(AgentError::Config(format!(
+            "eval suite compares two prompt versions, got {}",
+            suite.versions.len()
+        )));
+    }
+    let library = prompt_library()?;
+    if let Some(missing) = suite
+        .versions
+        .iter()
+        .find(|version| !library.has_version(&suite.ai_function, version))
+    {
+        return Err(AgentError::Config(format!(
+            "prompt {} has no version {}",
+            suite.ai_function, missing
+        )));
+    }
+
+    let mut scores: Vec<VersionScore> = Vec::new();
+    for version in &suite.versions {
+        scores.push(score_version(suite, version).await?);
+    }
+    Ok(scores)
+}
+
+fn rate(count: Option<usize>, cases: usize) -> String {
+    match count {
+        Some(count) if cases > 0 => format!(
+            "{}/{} ({:.0}%)",
+            count,
+            cases,
+            count as f64 * 100.0 / cases as f64
+        ),
+        Some(count) => format!("{}/{}", count, cases),
+        None => "-".to_string(),
+    }
+}
+
+pub fn comparison_table(scores: &[VersionScore]) -> String {
+    let mut rows: Vec<Vec<String>> = vec![[
+        "Version",
+        "Answered",
+        "Valid JSON",
+        "Schema",
+        "Usable",
+        "Builds",
+    ]
+    .map(String::from)
+    .to_vec()];
+    for score in scores {
+        rows.push(vec![
+            score.version.clone(),
+            rate(Some(score.answered), score.cases),
+            rate(score.valid_json, score.cases),
+            rate(score.schema_ok, score.cases),
+            rate(Some(score.usable), score.cases),
+            rate(score.built, score.cases),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+        .collect();
+    let mut table: String = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+// `auto-gippity eval <suite.toml>`, exchanges go to the run journal like a project run
+pub async fn run_eval(path: &Path) -> Result<(), AgentError> {
+    let suite: EvalSuite = EvalSuite::from_file(path)?;
+    let journal: Option<Arc<RunJournal>> = RunJournal::from_env()?.map(Arc::new);
+
+    let scores: Vec<VersionScore> = with_journal(journal.clone(), run_suite(&suite)).await?;
+    println!("\n{} over {} cases", suite.ai_function, suite.cases.len());
+    print!("{}", comparison_table(&scores));
+    if let Some(journal) = journal {
+        println!("Run journal: {}", journal.path().display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::llm_provider::with_provider;
+    use crate::helpers::test_support::replay_answers;
+
+    #[tokio::test]
+    async fn tests_versions_are_scored_side_by_side() {
+        let suite: EvalSuite = toml::from_str(
+            r#"
+ai_function = "print_site_urls"
+versions = ["builtin", "builtin"]
+
+[[cases]]
+name = "crypto prices"
+input = "build a website that shows crypto prices"
+"#,
+        )
+        .unwrap();
+
+        // Second version: two bad answers before a good one
+        let provider = replay_answers(&[
+            r#"["https://api.coingecko.com"]"#,
+            "not json",
+            "still not json",
+            r#"["https://api.binance.com"]"#,
+        ]);
+        let scores = with_provider(provider, async {
+            vec![
+                score_version(&suite, "builtin").await.unwrap(),
+                score_version(&suite, "builtin").await.unwrap(),
+            ]
+        })
+        .await;
+
+        assert_eq!(scores[0].valid_json, Some(1));
+        assert_eq!(scores[0].usable, 1);
+        assert_eq!(scores[1].valid_json, Some(0));
+        assert_eq!(scores[1].schema_ok, Some(0));
+        assert_eq!(scores[1].usable, 1);
+        assert_eq!(scores[1].built, None);
+
+        let table = comparison_table(&scores);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("Version  Answered"));
+        assert!(lines[1].contains("1/1 (100%)  1/1 (100%)"));
+        assert!(lines[2].contains("0/1 (0%)"));
+        assert!(lines[2].ends_with("-"));
+
+        let mut three = suite.clone();
+        three.versions.push("v2".to_string());
+        assert!(run_suite(&three).await.is_err());
+
+        // Every framework's prompts can be evaluated
+        assert_eq!(
+            eval_kind("print_axum_rest_api_endpoints"),
+            Some(EvalKind::Decoded)
+        );
+        assert_eq!(eval_kind("print_axum_webserver_code"), Some(EvalKind::Code));
+        assert_eq!(eval_kind("print_unknown"), None);
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::error::AgentError;
+use crate::models::agents::agent_traits::BackendFramework;
+
+// Reference code the backend prompts start from, not compiled into the crate
+pub const CODE_TEMPLATE_FILE: &str = "src/_code_template.rs";
+
+// A backend crate compiled into the binary, so a run needs no hand-prepared template folder.
+// {{crate_name}} in a file is replaced with the name of the generated crate.
+#[derive(Debug)]
+pub struct StarterTemplate {
+    pub name: &'static str,
+    pub framework: BackendFramework,
+    pub description: &'static str,
+    // Path in the generated crate and contents
+    pub files: &'static [(&'static str, &'static str)],
+}
+
+pub const STARTER_TEMPLATES: &[StarterTemplate] = &[
+    StarterTemplate {
+        name: "actix-web",
+        framework: BackendFramework::ActixWeb,
+        description: "actix-web server with CORS and a JSON file database (tasks and users)",
+        files: &[
+            (
+                "Cargo.toml",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/actix-web/Cargo.toml.template"
+                )),
+            ),
+            (
+                "src/main.rs",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/actix-web/main.rs"
+                )),
+            ),
+            (
+                CODE_TEMPLATE_FILE,
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/actix-web/main.rs"
+                )),
+            ),
+            (
+                "database.json",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/actix-web/database.json"
+                )),
+            ),
+        ],
+    },
+    StarterTemplate {
+        name: "axum",
+        framework: BackendFramework::Axum,
+        description: "axum server with CORS and a JSON file database (tasks and users)",
+        files: &[
+            (
+                "Cargo.toml",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/axum/Cargo.toml.template"
+                )),
+            ),
+            (
+                "src/main.rs",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/axum/main.rs"
+                )),
+            ),
+            (
+                CODE_TEMPLATE_FILE,
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/axum/main.rs"
+                )),
+            ),
+            (
+                "database.json",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/templates/axum/database.json"
+                )),
+            ),
+        ],
+    },
+];
+
+impl StarterTemplate {
+    pub fn find(name: &str) -> Result<&'static Self, AgentError> {
+        STARTER_TEMPLATES
+            .iter()
+            .find(|template| template.name == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = STARTER_TEMPLATES.iter().map(|t| t.name).collect();
+                AgentError::Config(format!(
+                    "no starter template {}, available: {}",
+                    name,
+                    names.join(", ")
+                ))
+            })
+    }
+
+    pub fn for_framework(framework: BackendFramework) -> Result<&'static Self, AgentError> {
+        STARTER_TEMPLATES
+            .iter()
+            .find(|template| template.framework == framework)
+            .ok_or_else(|| AgentError::Config(format!("no starter template for {}", framework)))
+    }
+
+    pub fn file(&self, path: &str) -> Option<&'static str> {
+        self.files
+            .iter()
+            .find(|(file_path, _)| *file_path == path)
+            .map(|(_, contents)| *contents)
+    }
+
+    pub fn code_template(&self) -> &'static str {
+        self.file(CODE_TEMPLATE_FILE).unwrap_or_default()
+    }
+
+    // Writes the crate into `dir`, which must not exist yet or be empty
+    pub fn generate(&self, dir: &Path, with_code_template: bool) -> Result<(), AgentError> {
+        let is_empty: bool = fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none());
+        if !is_empty {
+            return Err(AgentError::Config(format!(
+                "{} already exists and is not empty",
+                dir.display()
+            )));
+        }
+        let crate_name: String = crate_name(dir);
+
+        for (path, contents) in self.files {
+            if *path == CODE_TEMPLATE_FILE && !with_code_template {
+                continue;
+            }
+            let file_path: PathBuf = dir.join(path);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent).map_err(AgentError::io(format!(
+                    "Failed to create directory {}",
+                    parent.display()
+                )))?;
+            }
+            fs::write(&file_path, contents.replace("{{crate_name}}", &crate_name)).map_err(
+                AgentError::io(format!("Failed to write {}", file_path.display())),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The directory name as a valid package name
+fn crate_name(dir: &Path) -> String {
+    let name: String = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    match name.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => name,
+        _ => format!("backend_{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn tests_starter_template_is_generated() {
+        let dir = env::temp_dir()
+            .join(format!("auto_gippity_starter_{}", std::process::id()))
+            .join("My-Backend");
+        let template = StarterTemplate::find("actix-web").unwrap();
+        assert!(StarterTemplate::find("rocket").is_err());
+
+        template.generate(&dir, true).unwrap();
+        let manifest: toml::Table =
+            toml::from_str(&fs::read_to_string(dir.join("Cargo.toml")).unwrap()).unwrap();
+        assert_eq!(manifest["package"]["name"].as_str(), Some("my_backend"));
+        // What the backend prompt tells the model is installed
+        for dependency in [
+            "actix-web",
+            "actix-cors",
+            "serde",
+            "serde_json",
+            "tokio",
+            "reqwest",
+            "async-trait",
+        ] {
+            assert!(manifest["dependencies"].get(dependency).is_some());
+        }
+        assert_eq!(
+            fs::read_to_string(dir.join(CODE_TEMPLATE_FILE)).unwrap(),
+            template.code_template()
+        );
+        let database: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("database.json")).unwrap()).unwrap();
+        assert!(database["tasks"].is_object());
+
+        let axum = StarterTemplate::for_framework(BackendFramework::Axum).unwrap();
+        assert!(axum.code_template().contains("Router::new()"));
+
+        // Never over an existing project
+        assert!(template.generate(&dir, true).is_err());
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::error::AgentError;
+use crate::helpers::command_line::PrintCommand;
+use crate::models::general::budget::{Budget, BudgetExceeded};
+use crate::models::general::llm::{LlmResponse, TokenUsage};
+
+// USD per million tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.input + usage.completion_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+// Prices keyed by model name, a key also matches dated variants (gpt-4o matches gpt-4o-2024-08-06).
+// Read from the TOML file in LLM_PRICES_FILE, e.g.
+//   [gpt-4o]
+//   input = 2.5
+//   output = 10.0
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct PriceTable {
+    models: HashMap<String, ModelPrice>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let prices: [(&str, f64, f64); 5] = [
+            ("gpt-4o", 2.5, 10.0),
+            ("gpt-4o-mini", 0.15, 0.6),
+            ("claude-3-5-sonnet", 3.0, 15.0),
+            ("claude-3-5-haiku", 0.8, 4.0),
+            // Replayed cassettes that did not record a model
+            ("cassette", 0.0, 0.0),
+        ];
+        let models = prices
+            .into_iter()
+            .map(|(model, input, output)| (model.to_string(), ModelPrice { input, output }))
+            .collect();
+        Self { models }
+    }
+}
+
+impl PriceTable {
+    pub fn from_toml(contents: &str) -> Result<Self, AgentError> {
+        toml::from_str(contents)
+            .map_err(|e| AgentError::Config(format!("invalid price table: {}", e)))
+    }
+
+    // Built-in prices, with entries from LLM_PRICES_FILE added or overriding them
+    pub fn from_env() -> Result<Self, AgentError> {
+        dotenv().ok();
+        let mut table = Self::default();
+        if let Ok(path) = env::var("LLM_PRICES_FILE") {
+            let contents: String = fs::read_to_string(&path).map_err(AgentError::io(format!(
+                "Failed to read price table {}",
+                path
+            )))?;
+            table.models.extend(Self::from_toml(&contents)?.models);
+        }
+        Ok(table)
+    }
+
+    // Longest matching prefix wins so gpt-4o-mini is not priced as gpt-4o
+    pub fn price_for(&self, model: &str) -> Option<ModelPrice> {
+        self.models
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct UsageTotals {
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+    // Calls the provider reported no usage for, or whose model has no price
+    pub unpriced_calls: u32,
+    // Answers from the response cache, not counted as calls
+    pub cache_hits: u32,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: Option<&TokenUsage>, cost: Option<f64>) {
+        self.calls += 1;
+        if let Some(usage) = usage {
+            self.prompt_tokens += usage.prompt_tokens;
+            self.completion_tokens += usage.completion_tokens;
+        }
+        match cost {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced_calls += 1,
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+// Running totals of a project, per agent position and per ai_function
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct UsageLedger {
+    pub by_agent: BTreeMap<String, UsageTotals>,
+    pub by_function: BTreeMap<String, UsageTotals>,
+    pub total: UsageTotals,
+}
+
+#[derive(Debug)]
+pub struct UsageTracker {
+    prices: PriceTable,
+    budget: Budget,
+    ledger: Mutex<UsageLedger>,
+    started: Instant,
+    // Wall-clock of an agent starts with its first llm call
+    agent_started: Mutex<HashMap<String, Instant>>,
+}
+
+impl UsageTracker {
+    pub fn new(prices: PriceTable, budget: Budget) -> Self {
+        Self {
+            prices,
+            budget,
+            ledger: Mutex::new(UsageLedger::default()),
+            started: Instant::now(),
+            agent_started: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Run caps first, then the caps of the calling agent
+    pub fn check_budget(&self, agent: &str) -> Result<(), BudgetExceeded> {
+        let agent_elapsed = self
+            .agent_started
+            .lock()
+            .unwrap()
+            .entry(agent.to_string())
+            .or_insert_with(Instant::now)
+            .elapsed();
+        let ledger = self.ledger.lock().unwrap();
+
+        self.budget
+            .run
+            .check("run", &ledger.total, self.started.elapsed())?;
+        let agent_totals: UsageTotals = ledger.by_agent.get(agent).cloned().unwrap_or_default();
+        self.budget
+            .per_agent
+            .check(agent, &agent_totals, agent_elapsed)
+    }
+
+    pub fn record(&self, agent: &str, ai_function: &str, response: &LlmResponse) {
+        if response.cached {
+            let mut ledger = self.ledger.lock().unwrap();
+            ledger
+                .by_agent
+                .entry(agent.to_string())
+                .or_default()
+                .cache_hits += 1;
+            ledger
+                .by_function
+                .entry(ai_function.to_string())
+                .or_default()
+                .cache_hits += 1;
+            ledger.total.cache_hits += 1;
+            return;
+        }
+
+        let usage: Option<&TokenUsage> = response.usage.as_ref();
+        let cost: Option<f64> = usage.and_then(|usage| {
+            self.prices
+                .price_for(&response.model)
+                .map(|price| price.cost(usage))
+        });
+
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger
+            .by_agent
+            .entry(agent.to_string())
+            .or_default()
+            .add(usage, cost);
+        ledger
+            .by_function
+            .entry(ai_function.to_string())
+            .or_default()
+            .add(usage, cost);
+        ledger.total.add(usage, cost);
+    }
+
+    pub fn ledger(&self) -> UsageLedger {
+        self.ledger.lock().unwrap().clone()
+    }
+
+    pub fn print_summary(&self, agent_pos: &str) {
+        let ledger: UsageLedger = self.ledger();
+        let sections = [
+            ("agent", &ledger.by_agent),
+            ("ai_function", &ledger.by_function),
+        ];
+
+        for (label, rows) in sections {
+            for (name, totals) in rows {
+                let line: String = format!("{} {}: {}", label, name, format_totals(totals));
+                PrintCommand::UnitTest.print_agent_message(agent_pos, &line);
+            }
+        }
+        let line: String = format!("total: {}", format_totals(&ledger.total));
+        PrintCommand::UnitTest.print_agent_message(agent_pos, &line);
+        if ledger.total.unpriced_calls > 0 {
+            let line: String = format!(
+                "{} calls without usage or price, cost is a lower bound",
+                ledger.total.unpriced_calls
+            );
+            PrintCommand::Issue.print_agent_message(agent_pos, &line);
+        }
+    }
+
+    pub fn export(&self, path: &str) -> Result<(), AgentError> {
+        let report: String = serde_json::to_string_pretty(&self.ledger())
+            .map_err(|e| AgentError::Config(format!("usage report: {}", e)))?;
+        fs::write(path, report).map_err(AgentError::io(format!(
+            "Failed to write usage report {}",
+            path
+        )))
+    }
+}
+
+fn format_totals(totals: &UsageTotals) -> String {
+    format!(
+        "{} calls, {} tokens ({} prompt + {} completion), ${:.4}, {} cache hits",
+        totals.calls,
+        totals.total_tokens(),
+        totals.prompt_tokens,
+        totals.completion_tokens,
+        totals.cost_usd,
+        totals.cache_hits
+    )
+}
+
+tokio::task_local! {
+    static USAGE_TRACKER: Arc<UsageTracker>;
+}
+
+// Usage of every llm call made inside `fut` is booked on `tracker`
+pub async fn with_usage_tracker<F: Future>(tracker: Arc<UsageTracker>, fut: F) -> F::Output {
+    USAGE_TRACKER.scope(tracker, fut).await
+}
+
+// Always passes outside of with_usage_tracker
+pub fn check_budget(agent: &str) -> Result<(), AgentError> {
+    USAGE_TRACKER
+        .try_with(|tracker| tracker.check_budget(agent))
+        .unwrap_or(Ok(()))
+        .map_err(AgentError::Budget)
+}
+
+// No-op outside of with_usage_tracker
+pub fn record_usage(agent: &str, ai_function: &str, response: &LlmResponse) {
+    let _ = USAGE_TRACKER.try_with(|tracker| tracker.record(agent, ai_function, response));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(model: &str, prompt_tokens: u64, completion_tokens: u64) -> LlmResponse {
+        LlmResponse {
+            content: String::new(),
+            model: model.to_string(),
+            usage: Some(TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+            }),
+            cached: false,
+        }
+    }
+
+    #[test]
+    fn tests_price_table_prefers_longest_prefix() {
+        let table = PriceTable::default();
+        assert_eq!(table.price_for("gpt-4o-2024-08-06").unwrap().input, 2.5);
+        assert_eq!(table.price_for("gpt-4o-mini").unwrap().input, 0.15);
+        assert!(table.price_for("llama3.1").is_none());
+
+        let table = PriceTable::from_toml("[\"llama3.1\"]\ninput = 0.0\noutput = 0.0\n").unwrap();
+        assert!(table.price_for("llama3.1").is_some());
+    }
+
+    #[tokio::test]
+    async fn tests_usage_is_booked_per_agent_and_function() {
+        let tracker = Arc::new(UsageTracker::new(PriceTable::default(), Budget::default()));
+        with_usage_tracker(tracker.clone(), async {
+            record_usage(
+                "Architect",
+                "print_project_scope",
+                &response("gpt-4o", 1000, 100),
+            );
+            record_usage(
+                "Backend",
+                "print_backend_code",
+                &response("gpt-4o", 2000, 500),
+            );
+            record_usage(
+                "Backend",
+                "print_backend_code",
+                &response("llama3.1", 10, 10),
+            );
+        })
+        .await;
+        let mut cached = response("gpt-4o", 1000, 100);
+        cached.cached = true;
+        with_usage_tracker(tracker.clone(), async {
+            record_usage("Architect", "print_project_scope", &cached);
+        })
+        .await;
+        // Outside of a scope nothing is recorded
+        record_usage("Backend", "print_backend_code", &response("gpt-4o", 1, 1));
+
+        let ledger = tracker.ledger();
+        assert_eq!(ledger.total.calls, 3);
+        assert_eq!(ledger.total.unpriced_calls, 1);
+        assert_eq!(ledger.by_function["print_project_scope"].cache_hits, 1);
+        assert_eq!(ledger.by_agent["Backend"].total_tokens(), 2520);
+        assert!((ledger.by_agent["Architect"].cost_usd - 0.0035).abs() < 1e-9);
+        assert!((ledger.total.cost_usd - 0.0135).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn tests_budget_is_checked_per_run_and_agent() {
+        let mut budget = Budget::default();
+        budget.run.max_calls = Some(3);
+        budget.per_agent.max_tokens = Some(2000);
+        let tracker = Arc::new(UsageTracker::new(PriceTable::default(), budget));
+
+        with_usage_tracker(tracker, async {
+            record_usage("Architect", "print_project_scope", &response("gpt-4o", 1000, 100));
+            assert!(check_budget("Architect").is_ok());
+            record_usage("Backend", "print_backend_code", &response("gpt-4o", 2000, 500));
+            // Backend is over its own cap, Architect is not
+            assert!(matches!(check_budget("Backend"), Err(AgentError::Budget(e)) if e.scope == "Backend"));
+            assert!(check_budget("Architect").is_ok());
+            record_usage("Architect", "print_site_urls", &response("gpt-4o", 10, 10));
+            assert!(matches!(check_budget("Architect"), Err(AgentError::Budget(e)) if e.scope == "run"));
+        })
+        .await;
+
+        assert!(check_budget("Backend").is_ok());
+    }
+}
@@ -0,0 +1,204 @@
+use dotenv::dotenv;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::error::AgentError;
+use crate::models::agents::agent_traits::BackendFramework;
+use crate::models::general::journal::run_name;
+use crate::models::general::starter_templates::{StarterTemplate, CODE_TEMPLATE_FILE};
+
+// The run's own copy of the backend template crate (EXEC_PATH, or the built-in starter
+// template of the framework when it is not set), in <LLM_RUNS_DIR>/<run name>/backend next to the run
+// journal. Generated code is written, built and run there, and the directory is kept
+// after the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunWorkspace {
+    dir: PathBuf,
+}
+
+impl RunWorkspace {
+    pub fn create(
+        runs_dir: impl AsRef<Path>,
+        template_crate: impl AsRef<Path>,
+    ) -> Result<Self, AgentError> {
+        let template_crate: &Path = template_crate.as_ref();
+        let dir: PathBuf = fresh_dir(runs_dir.as_ref());
+        // Data files, build.rs, .cargo/config.toml ... the backend may depend on come along
+        fs::create_dir_all(&dir).map_err(AgentError::io(format!(
+            "Failed to create run workspace {}",
+            dir.display()
+        )))?;
+        // The runs directory may well be inside the template crate
+        let runs: PathBuf = fs::canonicalize(&dir).map_err(AgentError::io(format!(
+            "Failed to resolve {}",
+            dir.display()
+        )))?;
+        copy_tree(template_crate, &dir, &runs)?;
+        let code_template: PathBuf = dir.join(CODE_TEMPLATE_FILE);
+        if code_template.exists() {
+            fs::remove_file(&code_template).map_err(AgentError::io(format!(
+                "Failed to remove {}",
+                code_template.display()
+            )))?;
+        }
+
+        let workspace: Self = Self { dir };
+        isolate_manifest(&workspace.dir)?;
+        workspace.pin_dependencies()?;
+        Ok(workspace)
+    }
+
+    pub fn from_starter(
+        runs_dir: impl AsRef<Path>,
+        starter: &StarterTemplate,
+    ) -> Result<Self, AgentError> {
+        let dir: PathBuf = fresh_dir(runs_dir.as_ref());
+        starter.generate(&dir, false)?;
+
+        let workspace: Self = Self { dir };
+        isolate_manifest(&workspace.dir)?;
+        workspace.pin_dependencies()?;
+        Ok(workspace)
+    }
+
+    pub fn from_env(framework: BackendFramework) -> Result<Self, AgentError> {
+        dotenv().ok();
+        let runs_dir: String = env::var("LLM_RUNS_DIR").unwrap_or_else(|_| "runs".to_string());
+        match env::var("EXEC_PATH") {
+            Ok(template_crate) => Self::create(runs_dir, template_crate),
+            Err(_) => Self::from_starter(runs_dir, StarterTemplate::for_framework(framework)?),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // The template's lock file when it has one, otherwise one resolved now, so fix rounds
+    // build against the same dependency versions
+    fn pin_dependencies(&self) -> Result<(), AgentError> {
+        if self.dir.join("Cargo.lock").exists() {
+            return Ok(());
+        }
+
+        let status = Command::new("cargo")
+            .arg("generate-lockfile")
+            .current_dir(&self.dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(AgentError::io("Failed to run cargo generate-lockfile"))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(AgentError::Config(format!(
+                "could not pin dependencies of {}",
+                self.dir.display()
+            ))),
+        }
+    }
+}
+
+// Builds in a runs directory inside another Cargo workspace must not join it
+pub fn isolate_manifest(crate_dir: &Path) -> Result<(), AgentError> {
+    let manifest_path: PathBuf = crate_dir.join("Cargo.toml");
+    let manifest: String = fs::read_to_string(&manifest_path).map_err(AgentError::io(format!(
+        "Failed to read {}",
+        manifest_path.display()
+    )))?;
+    if manifest.lines().any(|line| line.trim() == "[workspace]") {
+        return Ok(());
+    }
+    fs::write(
+        &manifest_path,
+        format!("{}\n# Standalone crate\n[workspace]\n", manifest.trim_end()),
+    )
+    .map_err(AgentError::io(format!(
+        "Failed to write {}",
+        manifest_path.display()
+    )))
+}
+
+// A second project in the same process gets a directory of its own
+fn fresh_dir(runs_dir: &Path) -> PathBuf {
+    let run_dir: PathBuf = runs_dir.join(run_name());
+    (1..)
+        .map(|n| match n {
+            1 => run_dir.join("backend"),
+            n => run_dir.join(format!("backend-{}", n)),
+        })
+        .find(|dir| !dir.exists())
+        .unwrap_or_default()
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), AgentError> {
+    fs::copy(from, to)
+        .map(|_| ())
+        .map_err(AgentError::io(format!("Failed to copy {}", from.display())))
+}
+
+// Everything but build output, version control and the directories holding the copy
+fn copy_tree(from: &Path, to: &Path, copy_root: &Path) -> Result<(), AgentError> {
+    fs::create_dir_all(to).map_err(AgentError::io(format!(
+        "Failed to create directory {}",
+        to.display()
+    )))?;
+    let entries = fs::read_dir(from).map_err(AgentError::io(format!(
+        "Failed to read template crate {}",
+        from.display()
+    )))?;
+    for entry in entries {
+        let entry = entry.map_err(AgentError::io(format!(
+            "Failed to read template crate {}",
+            from.display()
+        )))?;
+        let path: PathBuf = entry.path();
+        let target: PathBuf = to.join(entry.file_name());
+        if path.is_dir() {
+            let holds_copy: bool =
+                fs::canonicalize(&path).is_ok_and(|dir| copy_root.starts_with(dir));
+            if !holds_copy && !matches!(entry.file_name().to_str(), Some("target" | ".git")) {
+                copy_tree(&path, &target, copy_root)?;
+            }
+        } else {
+            copy_file(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_support::fixture_path;
+
+    #[test]
+    fn tests_each_project_gets_a_fresh_copy_of_the_template() {
+        let runs_dir =
+            env::temp_dir().join(format!("auto_gippity_workspace_{}", std::process::id()));
+        let template = fixture_path("backend_template");
+
+        let first = RunWorkspace::create(&runs_dir, &template).unwrap();
+        fs::write(first.dir().join("src/main.rs"), "fn main() { broken }").unwrap();
+        let second = RunWorkspace::create(&runs_dir, &template).unwrap();
+
+        assert_eq!(first.dir().file_name().unwrap(), "backend");
+        assert_eq!(second.dir().file_name().unwrap(), "backend-2");
+        assert_eq!(
+            fs::read_to_string(second.dir().join("src/main.rs")).unwrap(),
+            fs::read_to_string(template.join("src/main.rs")).unwrap()
+        );
+        assert!(!second.dir().join(CODE_TEMPLATE_FILE).exists());
+        // Files outside src are part of the crate too
+        assert_eq!(
+            fs::read_to_string(second.dir().join("data/users.json")).unwrap(),
+            "[]\n"
+        );
+        assert!(second.dir().join("Cargo.lock").exists());
+        // The template crate itself is left alone
+        assert!(!template.join("Cargo.lock").exists());
+        fs::remove_dir_all(runs_dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tower_http::cors::CorsLayer;
+
+const DATABASE_FILE: &str = "database.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Task {
+    id: u64,
+    name: String,
+    completed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct User {
+    id: u64,
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Database {
+    tasks: HashMap<u64, Task>,
+    users: HashMap<u64, User>,
+}
+
+impl Database {
+    fn load_from_file() -> Self {
+        fs::read_to_string(DATABASE_FILE)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_to_file(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(DATABASE_FILE, contents)
+    }
+
+    fn insert(&mut self, task: Task) {
+        self.tasks.insert(task.id, task);
+    }
+
+    fn get(&self, id: &u64) -> Option<&Task> {
+        self.tasks.get(id)
+    }
+
+    fn get_all(&self) -> Vec<Task> {
+        self.tasks.values().cloned().collect()
+    }
+
+    fn delete(&mut self, id: &u64) -> Option<Task> {
+        self.tasks.remove(id)
+    }
+
+    fn insert_user(&mut self, user: User) {
+        self.users.insert(user.id, user);
+    }
+
+    fn get_user_by_name(&self, username: &str) -> Option<&User> {
+        self.users.values().find(|user| user.username == username)
+    }
+}
+
+type AppState = Arc<Mutex<Database>>;
+
+async fn create_task(State(db): State<AppState>, Json(task): Json<Task>) -> StatusCode {
+    let mut db = db.lock().unwrap();
+    db.insert(task);
+    let _ = db.save_to_file();
+    StatusCode::OK
+}
+
+async fn read_task(
+    State(db): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<Task>, StatusCode> {
+    let db = db.lock().unwrap();
+    db.get(&id).cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn read_all_tasks(State(db): State<AppState>) -> Json<Vec<Task>> {
+    Json(db.lock().unwrap().get_all())
+}
+
+async fn update_task(State(db): State<AppState>, Json(task): Json<Task>) -> StatusCode {
+    let mut db = db.lock().unwrap();
+    db.insert(task);
+    let _ = db.save_to_file();
+    StatusCode::OK
+}
+
+async fn delete_task(State(db): State<AppState>, Path(id): Path<u64>) -> StatusCode {
+    let mut db = db.lock().unwrap();
+    db.delete(&id);
+    let _ = db.save_to_file();
+    StatusCode::OK
+}
+
+async fn register(State(db): State<AppState>, Json(user): Json<User>) -> StatusCode {
+    let mut db = db.lock().unwrap();
+    db.insert_user(user);
+    let _ = db.save_to_file();
+    StatusCode::OK
+}
+
+async fn login(State(db): State<AppState>, Json(user): Json<User>) -> (StatusCode, &'static str) {
+    let db = db.lock().unwrap();
+    match db.get_user_by_name(&user.username) {
+        Some(stored) if stored.password == user.password => (StatusCode::OK, "Logged in!"),
+        _ => (StatusCode::BAD_REQUEST, "Invalid username or password"),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let state: AppState = Arc::new(Mutex::new(Database::load_from_file()));
+
+    let app = Router::new()
+        .route(
+            "/task",
+            get(read_all_tasks).post(create_task).put(update_task),
+        )
+        .route("/task/:id", get(read_task).delete(delete_task))
+        .route("/register", post(register))
+        .route("/login", post(login))
+        .layer(CorsLayer::permissive())
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:8080")
+        .await
+        .expect("Failed to bind port 8080");
+    axum::serve(listener, app).await.expect("Server failed");
+}